@@ -1,8 +1,9 @@
 use super::LoadedFile;
 
 use std::convert::{TryFrom, TryInto};
-use std::io::Cursor;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, RwLock};
 
 /// The different kinds of files we can open.
@@ -24,7 +25,26 @@ impl TryFrom<&str> for AudioFileKind {
     }
 }
 
+/// The number of interleaved channels in [Samples].
+pub const CHANNELS: usize = 2;
+
+/// Makes interleaved samples with any number of channels stereo. Mono is
+/// played on both channels, and channels after the first two are dropped.
+fn to_stereo(data: Vec<f32>, channels: usize) -> Vec<f32> {
+    match channels {
+        CHANNELS => data,
+        1 => data.iter().flat_map(|&x| std::iter::repeat(x).take(CHANNELS)).collect(),
+        0 => Vec::new(),
+        _ => data
+            .chunks_exact(channels)
+            .flat_map(|frame| frame[..CHANNELS].iter().copied())
+            .collect(),
+    }
+}
+
 /// Actual audio data.
+///
+/// The data is interleaved stereo, so a frame is [CHANNELS] samples long.
 #[derive(Clone)]
 pub struct Samples {
     data: Vec<f32>,
@@ -46,6 +66,86 @@ impl Samples {
     pub fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
+
+    /// The number of frames, i.e. samples per channel.
+    pub fn num_frames(&self) -> usize {
+        self.data.len() / CHANNELS
+    }
+
+    /// How long the samples are in seconds.
+    pub fn duration(&self) -> f32 {
+        self.num_frames() as f32 / self.sample_rate as f32
+    }
+
+    /// Adds the other samples to the end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sample rates differ.
+    pub fn append(&mut self, other: &Samples) {
+        assert_eq!(self.sample_rate, other.sample_rate);
+        self.data.extend_from_slice(&other.data);
+    }
+
+    /// Copies out the frames in `[start, end)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn slice(&self, start: usize, end: usize) -> Samples {
+        Samples {
+            data: self.data[start * CHANNELS..end * CHANNELS].to_vec(),
+            sample_rate: self.sample_rate,
+        }
+    }
+
+    /// Scales the samples so the loudest one is at full volume.
+    /// Silence is left alone.
+    pub fn normalize(&mut self) {
+        let peak = self.data.iter().fold(0.0, |peak: f32, x| peak.max(x.abs()));
+        if peak > 0.0 {
+            self.scale(1.0 / peak);
+        }
+    }
+
+    /// Scales the samples so their root mean square is `target_rms`, which
+    /// is closer to how loud they sound than the peak. Loud enough targets
+    /// can make the samples clip. Silence is left alone.
+    pub fn rms_normalize(&mut self, target_rms: f32) {
+        if self.data.is_empty() {
+            return;
+        }
+        let rms = (self.data.iter().map(|x| x * x).sum::<f32>() / self.data.len() as f32).sqrt();
+        if rms > 0.0 {
+            self.scale(target_rms / rms);
+        }
+    }
+
+    fn scale(&mut self, gain: f32) {
+        for x in self.data.iter_mut() {
+            *x *= gain;
+        }
+    }
+
+    /// Plays both samples at the same time, with the other samples scaled by
+    /// `gain`. The result is as long as the longest of the two.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sample rates differ.
+    pub fn mix(&self, other: &Samples, gain: f32) -> Samples {
+        assert_eq!(self.sample_rate, other.sample_rate);
+        let len = self.data.len().max(other.data.len());
+        let data = (0..len)
+            .map(|i| {
+                self.data.get(i).unwrap_or(&0.0) + other.data.get(i).unwrap_or(&0.0) * gain
+            })
+            .collect();
+        Samples {
+            data,
+            sample_rate: self.sample_rate,
+        }
+    }
 }
 
 pub struct Audio {
@@ -69,6 +169,16 @@ impl Audio {
         Arc::clone(&self.samples)
     }
 
+    /// How long the sound is in seconds.
+    pub fn duration(&self) -> f32 {
+        self.samples.read().unwrap().duration()
+    }
+
+    /// The file the sound was loaded from.
+    pub fn file(&self) -> &LoadedFile {
+        &self.data
+    }
+
     pub fn reload(&mut self) -> bool {
         if let Some(bytes) = self.data.reload() {
             *self.samples.write().unwrap() = load_data(bytes, self.kind);
@@ -79,6 +189,76 @@ impl Audio {
     }
 }
 
+/// An OGG file that's decoded a little at a time, instead of all at once.
+/// Good for long music tracks.
+pub struct OggStream {
+    file: PathBuf,
+    sample_rate: u32,
+    /// None until a clone is read from, see the Clone impl.
+    reader: Option<lewton::inside_ogg::OggStreamReader<BufReader<File>>>,
+}
+
+impl OggStream {
+    pub fn open(file: PathBuf) -> Option<Self> {
+        let reader = Self::open_reader(&file)?;
+        Some(Self {
+            file,
+            sample_rate: reader.ident_hdr.audio_sample_rate,
+            reader: Some(reader),
+        })
+    }
+
+    fn open_reader(file: &Path) -> Option<lewton::inside_ogg::OggStreamReader<BufReader<File>>> {
+        lewton::inside_ogg::OggStreamReader::new(BufReader::new(File::open(file).ok()?)).ok()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Decodes the next packet and appends the interleaved samples to `data`.
+    /// Returns false when the end of the file is reached, or if it can't be opened.
+    pub fn read_packet(&mut self, data: &mut Vec<f32>) -> bool {
+        if self.reader.is_none() {
+            self.reader = Self::open_reader(&self.file);
+        }
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return false,
+        };
+        match reader.read_dec_packet_itl() {
+            Ok(Some(packet)) => {
+                let packet = packet.into_iter().map(|i| i as f32 / i16::MAX as f32).collect();
+                let channels = reader.ident_hdr.audio_channels as usize;
+                data.extend(to_stereo(packet, channels));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Goes back to the start of the file.
+    pub fn rewind(&mut self) -> bool {
+        match self.reader.as_mut() {
+            Some(reader) => reader.seek_absgp_pg(0).is_ok(),
+            // Starts from the beginning when it's opened.
+            None => true,
+        }
+    }
+}
+
+/// The clone opens the file again when it's first read, starting from the
+/// beginning. If the file is gone by then, the clone is silent.
+impl Clone for OggStream {
+    fn clone(&self) -> Self {
+        Self {
+            file: self.file.clone(),
+            sample_rate: self.sample_rate,
+            reader: None,
+        }
+    }
+}
+
 pub fn load_data(bytes: Vec<u8>, kind: AudioFileKind) -> Samples {
     match kind {
         AudioFileKind::Ogg => load_ogg(bytes),
@@ -93,7 +273,7 @@ pub fn load_wav(bytes: Vec<u8>) -> Samples {
         _ => todo!("Only WAV containing floats are currently supported"),
     };
     Samples {
-        data,
+        data: to_stereo(data, header.channel_count as usize),
         sample_rate: header.sampling_rate,
     }
 }
@@ -107,7 +287,97 @@ pub fn load_ogg(bytes: Vec<u8>) -> Samples {
         data.append(&mut frame.into_iter().map(|i| i as f32 / i16::MAX as f32).collect());
     }
     Samples {
-        data,
+        data: to_stereo(data, reader.ident_hdr.audio_channels as usize),
         sample_rate: reader.ident_hdr.audio_sample_rate,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_adds_to_the_end() {
+        let mut samples = Samples::new(vec![0.1, 0.2], 44100);
+        samples.append(&Samples::new(vec![0.3, 0.4, 0.5, 0.6], 44100));
+        assert_eq!(samples.data(), &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6]);
+        assert_eq!(samples.num_frames(), 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn append_with_other_sample_rate() {
+        let mut samples = Samples::new(vec![0.1, 0.2], 44100);
+        samples.append(&Samples::new(vec![0.3, 0.4], 48000));
+    }
+
+    #[test]
+    fn slice_counts_frames() {
+        let samples = Samples::new(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 44100);
+        assert_eq!(samples.slice(1, 3).data(), &[0.3, 0.4, 0.5, 0.6]);
+        assert_eq!(samples.slice(1, 3).sample_rate(), 44100);
+        assert!(samples.slice(2, 2).data().is_empty());
+        assert!(samples.slice(3, 3).data().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_out_of_bounds() {
+        Samples::new(vec![0.1, 0.2], 44100).slice(0, 2);
+    }
+
+    #[test]
+    fn mix_is_as_long_as_the_longest() {
+        let short = Samples::new(vec![0.5, 0.5], 44100);
+        let long = Samples::new(vec![1.0, 1.0, 1.0, 1.0], 44100);
+        assert_eq!(short.mix(&long, 0.5).data(), &[1.0, 1.0, 0.5, 0.5]);
+        assert_eq!(long.mix(&short, 2.0).data(), &[2.0, 2.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn to_stereo_keeps_stereo() {
+        assert_eq!(to_stereo(vec![0.1, 0.2, 0.3, 0.4], 2), vec![0.1, 0.2, 0.3, 0.4]);
+    }
+
+    #[test]
+    fn to_stereo_plays_mono_on_both_channels() {
+        assert_eq!(to_stereo(vec![0.1, 0.2], 1), vec![0.1, 0.1, 0.2, 0.2]);
+    }
+
+    #[test]
+    fn to_stereo_drops_extra_channels() {
+        assert_eq!(to_stereo(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 3), vec![0.1, 0.2, 0.4, 0.5]);
+    }
+
+    #[test]
+    fn to_stereo_without_channels() {
+        assert!(to_stereo(vec![0.1, 0.2], 0).is_empty());
+    }
+
+    #[test]
+    fn normalize_scales_the_peak_to_one() {
+        let mut samples = Samples::new(vec![0.25, -0.5, 0.125, 0.0], 44100);
+        samples.normalize();
+        assert_eq!(samples.data(), &[0.5, -1.0, 0.25, 0.0]);
+    }
+
+    #[test]
+    fn rms_normalize_scales_to_the_target() {
+        let mut samples = Samples::new(vec![0.5, -0.5, 0.5, -0.5], 44100);
+        samples.rms_normalize(0.25);
+        assert_eq!(samples.data(), &[0.25, -0.25, 0.25, -0.25]);
+    }
+
+    #[test]
+    fn normalize_leaves_silence_alone() {
+        let mut samples = Samples::new(vec![0.0; 4], 44100);
+        samples.normalize();
+        samples.rms_normalize(0.5);
+        assert_eq!(samples.data(), &[0.0; 4]);
+
+        let mut empty = Samples::new(Vec::new(), 44100);
+        empty.normalize();
+        empty.rms_normalize(0.5);
+        assert!(empty.data().is_empty());
+    }
+}
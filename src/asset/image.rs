@@ -7,32 +7,205 @@ pub struct Image {
     pub width: Pixels,
     pub height: Pixels,
     pub texture_data: Vec<u8>,
-    pub data: LoadedFile,
+    /// The file the image was loaded from, if any.
+    pub data: Option<LoadedFile>,
+    /// Set when the pixels have been changed, and need to be uploaded again.
+    pub dirty: bool,
+    /// See [Image::premultiply_alpha].
+    premultiplied: bool,
+    /// See [Image::animation].
+    animation: Option<Animation>,
+}
+
+/// The frames of an animated image, placed left to right in the image.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    /// The size of one frame, use it as the tile size of the sprite sheet.
+    pub frame_size: (Pixels, Pixels),
+    /// How long each frame is shown, in seconds.
+    pub delays: Vec<f32>,
+}
+
+impl Animation {
+    pub fn frames(&self) -> usize {
+        self.delays.len()
+    }
+
+    /// The length of one loop of the animation, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.delays.iter().sum()
+    }
+
+    /// Which frame is shown `time` seconds into the animation. Loops.
+    pub fn frame_at(&self, time: f32) -> usize {
+        let duration = self.duration();
+        if duration <= 0.0 {
+            return 0;
+        }
+        let mut time = time.rem_euclid(duration);
+        for (i, delay) in self.delays.iter().enumerate() {
+            if time < *delay {
+                return i;
+            }
+            time -= delay;
+        }
+        self.frames() - 1
+    }
 }
 
 impl Image {
+    /// # Panics
+    ///
+    /// Panics if the file isn't an image we know how to load, see [Image::try_new].
     pub fn new(file: PathBuf) -> Self {
+        let name = file.display().to_string();
+        Self::try_new(file).unwrap_or_else(|| panic!("failed to load image {}", name))
+    }
+
+    /// Like [Image::new], but returns None if the file isn't an image we
+    /// know how to load, like a broken GIF.
+    pub fn try_new(file: PathBuf) -> Option<Self> {
         let (data, bytes) = LoadedFile::new(file);
         let mut ret = Self {
             width: 0,
             height: 0,
             texture_data: Vec::new(),
-            data,
+            data: Some(data),
+            dirty: false,
+            premultiplied: false,
+            animation: None,
         };
-        ret.load_data(bytes);
-        ret
+        if ret.load_data(bytes) {
+            Some(ret)
+        } else {
+            None
+        }
+    }
+
+    /// Creates an image from raw RGBA-data, starting from the bottom left.
+    ///
+    /// These images aren't backed by a file and are never reloaded.
+    pub fn from_pixels(width: Pixels, height: Pixels, texture_data: Vec<u8>) -> Self {
+        assert_eq!(texture_data.len(), width * height * 4);
+        Self {
+            width,
+            height,
+            texture_data,
+            data: None,
+            dirty: false,
+            premultiplied: false,
+            animation: None,
+        }
+    }
+
+    /// Creates an image where all pixels have the same RGBA-color.
+    pub fn solid(width: Pixels, height: Pixels, color: [u8; 4]) -> Self {
+        Self::from_pixels(width, height, color.repeat(width * height))
+    }
+
+    /// Creates an image that fades from one RGBA-color at the top
+    /// to another at the bottom.
+    pub fn vertical_gradient(width: Pixels, height: Pixels, top: [u8; 4], bottom: [u8; 4]) -> Self {
+        let mut texture_data = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            // The data starts from the bottom.
+            let f = if height > 1 { y as f32 / (height - 1) as f32 } else { 0.0 };
+            let color: Vec<u8> = bottom
+                .iter()
+                .zip(top.iter())
+                .map(|(&b, &t)| (b as f32 * (1.0 - f) + t as f32 * f).round() as u8)
+                .collect();
+            for _ in 0..width {
+                texture_data.extend_from_slice(&color);
+            }
+        }
+        Self::from_pixels(width, height, texture_data)
+    }
+
+    fn pixel_index(&self, x: Pixels, y: Pixels) -> Option<usize> {
+        if x < self.width && y < self.height {
+            Some((y * self.width + x) * 4)
+        } else {
+            None
+        }
+    }
+
+    /// The RGBA-color of a pixel, starting from the bottom left.
+    /// Returns None if the pixel is outside the image.
+    pub fn get_pixel(&self, x: Pixels, y: Pixels) -> Option<[u8; 4]> {
+        let i = self.pixel_index(x, y)?;
+        let p = &self.texture_data[i..i + 4];
+        Some([p[0], p[1], p[2], p[3]])
+    }
+
+    /// Changes the RGBA-color of a pixel, starting from the bottom left,
+    /// and marks the image as [dirty](Image::dirty).
+    /// Returns None if the pixel is outside the image.
+    pub fn set_pixel(&mut self, x: Pixels, y: Pixels, color: [u8; 4]) -> Option<()> {
+        let i = self.pixel_index(x, y)?;
+        self.texture_data[i..i + 4].copy_from_slice(&color);
+        self.dirty = true;
+        Some(())
     }
 
+    /// Multiplies the color of every pixel by its alpha. Stops dark fringes
+    /// from showing up around soft edges, but has to be drawn with
+    /// [BlendMode::PremultipliedAlpha](crate::renderer::BlendMode::PremultipliedAlpha).
+    ///
+    /// Stays premultiplied when the image is reloaded.
+    pub fn premultiply_alpha(&mut self) {
+        if self.premultiplied {
+            return;
+        }
+        self.premultiplied = true;
+        self.dirty = true;
+        for pixel in self.texture_data.chunks_exact_mut(4) {
+            let a = pixel[3] as u16;
+            for c in pixel[..3].iter_mut() {
+                *c = ((*c as u16 * a + 127) / 255) as u8;
+            }
+        }
+    }
+
+    pub fn premultiplied(&self) -> bool {
+        self.premultiplied
+    }
+
+    /// Set if the image was loaded from an animated GIF. The frames are placed
+    /// left to right, so they can be used with
+    /// [SpriteSheet::grid](crate::renderer::SpriteSheet::grid) and `(frame, 0)`.
+    ///
+    /// Needs the `gif` feature.
+    pub fn animation(&self) -> Option<&Animation> {
+        self.animation.as_ref()
+    }
+
+    /// Keeps the old pixels if the file can't be loaded, like when it's
+    /// only half written.
     pub fn reload(&mut self) -> bool {
-        if let Some(bytes) = self.data.reload() {
-            self.load_data(bytes);
+        if let Some(bytes) = self.data.as_mut().and_then(|data| data.reload()) {
+            if !self.load_data(bytes) {
+                return false;
+            }
+            if self.premultiplied {
+                self.premultiplied = false;
+                self.premultiply_alpha();
+            }
             true
         } else {
             false
         }
     }
 
-    fn load_data(&mut self, bytes: Vec<u8>) {
+    /// Returns false, and changes nothing, if the data can't be decoded.
+    fn load_data(&mut self, bytes: Vec<u8>) -> bool {
+        #[cfg(feature = "gif")]
+        {
+            if bytes.starts_with(b"GIF8") {
+                return self.load_gif(bytes).is_some();
+            }
+        }
+
         let mut w: i32 = 0;
         let mut h: i32 = 0;
         let mut comp: i32 = 4;
@@ -48,6 +221,9 @@ impl Image {
                 &mut comp,
                 4,
             );
+            if stb_image.is_null() {
+                return false;
+            }
             self.texture_data = Vec::from_raw_parts(
                 stb_image as *mut u8,
                 (w * h * 4) as usize,
@@ -56,5 +232,112 @@ impl Image {
         }
         self.width = w as usize;
         self.height = h as usize;
+        self.animation = None;
+        true
+    }
+
+    /// Returns None, and changes nothing, if the GIF is broken.
+    #[cfg(feature = "gif")]
+    fn load_gif(&mut self, bytes: Vec<u8>) -> Option<()> {
+        use gif::{ColorOutput, DecodeOptions, DisposalMethod};
+
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = options.read_info(bytes.as_slice()).ok()?;
+        let (w, h) = (decoder.width() as usize, decoder.height() as usize);
+
+        // The frames are drawn on top of each other, from the top left.
+        let mut canvas = vec![0; w * h * 4];
+        let mut frames = Vec::new();
+        let mut delays = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().ok()? {
+            let previous = canvas.clone();
+            let (left, top) = (frame.left as usize, frame.top as usize);
+            let (fw, fh) = (frame.width as usize, frame.height as usize);
+            for (i, pixel) in frame.buffer.chunks_exact(4).enumerate() {
+                let (x, y) = (left + i % fw, top + i / fw);
+                // Transparent pixels let the previous frame through.
+                if x < w && y < h && pixel[3] != 0 {
+                    let j = (y * w + x) * 4;
+                    canvas[j..j + 4].copy_from_slice(pixel);
+                }
+            }
+            frames.push(canvas.clone());
+            // The delay is in hundredths of a second.
+            delays.push(frame.delay as f32 / 100.0);
+
+            match frame.dispose {
+                DisposalMethod::Background => {
+                    for y in top..(top + fh).min(h) {
+                        for x in left..(left + fw).min(w) {
+                            let j = (y * w + x) * 4;
+                            canvas[j..j + 4].copy_from_slice(&[0; 4]);
+                        }
+                    }
+                }
+                DisposalMethod::Previous => canvas = previous,
+                DisposalMethod::Any | DisposalMethod::Keep => {}
+            }
+        }
+        if frames.is_empty() {
+            return None;
+        }
+
+        // Place the frames next to each other, starting from the bottom left.
+        let width = w * frames.len();
+        let mut texture_data = vec![0; width * h * 4];
+        for (f, frame) in frames.iter().enumerate() {
+            for y in 0..h {
+                let src = y * w * 4;
+                let dst = ((h - 1 - y) * width + f * w) * 4;
+                texture_data[dst..dst + w * 4].copy_from_slice(&frame[src..src + w * 4]);
+            }
+        }
+
+        self.texture_data = texture_data;
+        self.width = width;
+        self.height = h;
+        self.animation = Some(Animation {
+            frame_size: (w, h),
+            delays,
+        });
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn animation(delays: &[f32]) -> Animation {
+        Animation {
+            frame_size: (8, 8),
+            delays: delays.to_vec(),
+        }
+    }
+
+    #[test]
+    fn frame_at_follows_the_delays() {
+        let animation = animation(&[0.5, 0.25, 0.25]);
+        assert_eq!(animation.frame_at(0.0), 0);
+        assert_eq!(animation.frame_at(0.4), 0);
+        assert_eq!(animation.frame_at(0.5), 1);
+        assert_eq!(animation.frame_at(0.6), 1);
+        assert_eq!(animation.frame_at(0.9), 2);
+    }
+
+    #[test]
+    fn frame_at_loops() {
+        let animation = animation(&[0.5, 0.25, 0.25]);
+        assert_eq!(animation.frame_at(1.0), 0);
+        assert_eq!(animation.frame_at(1.6), 1);
+        assert_eq!(animation.frame_at(-0.1), 2);
+        assert_eq!(animation.frame_at(-0.6), 0);
+    }
+
+    #[test]
+    fn frame_at_without_duration() {
+        assert_eq!(animation(&[]).frame_at(1.0), 0);
+        assert_eq!(animation(&[0.0, 0.0]).frame_at(1.0), 0);
     }
 }
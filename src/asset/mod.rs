@@ -11,17 +11,21 @@
 //! ```
 //!
 //! When building with `cfg(debug_assertions)` (i.e. without `--release`) assets are hot-reloaded.
+//! By default the files are polled for changes. With the `watch` feature the files are
+//! watched for filesystem events instead, falling back to polling if that doesn't work.
 
 pub mod audio;
 pub mod image;
 pub mod font;
+#[cfg(feature = "watch")]
+mod watch;
 
 pub use audio::Audio;
-pub use image::Image;
+pub use image::{Animation, Image};
 pub use font::Font;
 
 use std::ops::Index;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 /// A marker type for the unit pixels.
@@ -86,17 +90,68 @@ impl AssetSystem {
     }
 
     /// Load a new image from disk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file isn't an image we know how to load, see [AssetSystem::try_load_image].
     pub fn load_image(&mut self, file: PathBuf) -> ImageAssetID {
+        let name = file.display().to_string();
+        self.try_load_image(file)
+            .unwrap_or_else(|| panic!("failed to load image {}", name))
+    }
+
+    /// Like [AssetSystem::load_image], but returns None if the file isn't
+    /// an image we know how to load. Nothing is added then.
+    pub fn try_load_image(&mut self, file: PathBuf) -> Option<ImageAssetID> {
+        let image = Image::try_new(file)?;
+        let id = self.images.len();
+        self.images.push(image);
+        Some(ImageAssetID(id))
+    }
+
+    /// Load an animated GIF from disk. The frames are placed left to right in the image.
+    ///
+    /// Needs the `gif` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file isn't a GIF we can load, see [AssetSystem::try_load_animation].
+    #[cfg(feature = "gif")]
+    pub fn load_animation(&mut self, file: PathBuf) -> (ImageAssetID, Animation) {
+        let name = file.display().to_string();
+        self.try_load_animation(file)
+            .unwrap_or_else(|| panic!("failed to load animation {}", name))
+    }
+
+    /// Like [AssetSystem::load_animation], but returns None if the file isn't
+    /// a GIF we can load. Nothing is added then.
+    #[cfg(feature = "gif")]
+    pub fn try_load_animation(&mut self, file: PathBuf) -> Option<(ImageAssetID, Animation)> {
+        let image = Image::try_new(file)?;
+        let animation = image.animation()?.clone();
         let id = self.images.len();
-        self.images.push(Image::new(file));
-        ImageAssetID(id)
+        self.images.push(image);
+        Some((ImageAssetID(id), animation))
     }
 
     /// Load a new sound from disk.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file isn't a sound we know how to load, see [AssetSystem::try_load_audio].
     pub fn load_audio(&mut self, file: PathBuf) -> AudioAssetID {
+        let name = file.display().to_string();
+        self.try_load_audio(file)
+            .unwrap_or_else(|| panic!("unknown audio format {}", name))
+    }
+
+    /// Like [AssetSystem::load_audio], but returns None if the file isn't
+    /// a sound we know how to load. Nothing is added then.
+    pub fn try_load_audio(&mut self, file: PathBuf) -> Option<AudioAssetID> {
+        let audio = Audio::new(file)?;
         let id = self.audio.len();
-        self.audio.push(Audio::new(file).unwrap());
-        AudioAssetID(id)
+        self.audio.push(audio);
+        Some(AudioAssetID(id))
     }
 
     /// Load a new font from disk.
@@ -106,6 +161,30 @@ impl AssetSystem {
         FontAssetID(id)
     }
 
+    /// All loaded images, in the order they were loaded.
+    pub fn images(&self) -> impl Iterator<Item = (ImageAssetID, &Image)> {
+        self.images.iter().enumerate().map(|(i, image)| (ImageAssetID(i), image))
+    }
+
+    /// All loaded sounds, in the order they were loaded.
+    pub fn audio(&self) -> impl Iterator<Item = (AudioAssetID, &Audio)> {
+        self.audio.iter().enumerate().map(|(i, audio)| (AudioAssetID(i), audio))
+    }
+
+    /// All loaded fonts, in the order they were loaded.
+    pub fn fonts(&self) -> impl Iterator<Item = (FontAssetID, &Font)> {
+        self.fonts.iter().enumerate().map(|(i, font)| (FontAssetID(i), font))
+    }
+
+    /// Whether the ID refers to a loaded asset. IDs returned by the asset
+    /// system always do, but IDs from `from_usize` might not.
+    pub fn is_valid(&self, id: AssetID) -> bool {
+        match id {
+            AssetID::Image(id) => id.0 < self.images.len(),
+            AssetID::Audio(id) => id.0 < self.audio.len(),
+            AssetID::Font(id) => id.0 < self.fonts.len(),
+        }
+    }
 
     pub fn reload(&mut self) {
         // Image and Font assets are reloaded by the renderer, which also uploads them.
@@ -146,6 +225,32 @@ pub struct LoadedFile {
     pub file: PathBuf,
     pub countdown: usize,
     pub last_modified: SystemTime,
+    /// Set if the file is watched for filesystem events, instead of polled.
+    watched: Option<PathBuf>,
+}
+
+#[cfg(feature = "watch")]
+fn watch_file(file: &Path) -> Option<PathBuf> {
+    if cfg!(debug_assertions) {
+        watch::watch(file)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(feature = "watch"))]
+fn watch_file(_: &Path) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(feature = "watch")]
+fn take_modified(watched: &Path) -> bool {
+    watch::take_modified(watched)
+}
+
+#[cfg(not(feature = "watch"))]
+fn take_modified(_: &Path) -> bool {
+    false
 }
 
 impl LoadedFile {
@@ -159,6 +264,7 @@ impl LoadedFile {
             std::fs::read(&file).expect(&format!("asset file {} not found", file.display()));
         (
             Self {
+                watched: watch_file(&file),
                 file,
                 last_modified,
                 countdown: 0,
@@ -170,8 +276,16 @@ impl LoadedFile {
     /// Return the file data if it has been modified since it was last read.
     ///
     /// Modification is checked using [std::fs::metadata] and as such might not work on all
-    /// operating systems.
+    /// operating systems. With the `watch` feature, filesystem events are used instead.
     pub fn reload(&mut self) -> Option<Vec<u8>> {
+        if let Some(watched) = &self.watched {
+            return if take_modified(watched) {
+                std::fs::read(&self.file).ok()
+            } else {
+                None
+            };
+        }
+
         if cfg!(debug_assertions) {
             match std::fs::metadata(&self.file)
                 .ok()
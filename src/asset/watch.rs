@@ -0,0 +1,76 @@
+//! Event driven file watching, used for hot-reloading when the `watch`
+//! feature is enabled.
+//!
+//! The directories of the watched files are watched, since a lot of editors
+//! save by replacing the file.
+
+use lazy_static::lazy_static;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::Duration;
+
+lazy_static! {
+    static ref WATCHER: Mutex<Option<FileWatcher>> = Mutex::new(FileWatcher::new());
+}
+
+// How long to wait for more events before telling us about a change.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+struct FileWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<DebouncedEvent>,
+    directories: HashSet<PathBuf>,
+    modified: HashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    fn new() -> Option<Self> {
+        let (tx, events) = channel();
+        let watcher = RecommendedWatcher::new(tx, DEBOUNCE).ok()?;
+        Some(Self {
+            watcher,
+            events,
+            directories: HashSet::new(),
+            modified: HashSet::new(),
+        })
+    }
+}
+
+/// Starts watching the file. Returns the path to check with [take_modified],
+/// or None if the file can't be watched.
+pub fn watch(file: &Path) -> Option<PathBuf> {
+    let file = file.canonicalize().ok()?;
+    let directory = file.parent()?.to_path_buf();
+
+    let mut watcher = WATCHER.lock().unwrap();
+    let watcher = watcher.as_mut()?;
+    if !watcher.directories.contains(&directory) {
+        watcher.watcher.watch(&directory, RecursiveMode::NonRecursive).ok()?;
+        watcher.directories.insert(directory);
+    }
+    Some(file)
+}
+
+/// Checks if the file has been modified since the last call.
+pub fn take_modified(file: &Path) -> bool {
+    let mut watcher = WATCHER.lock().unwrap();
+    let watcher = match watcher.as_mut() {
+        Some(watcher) => watcher,
+        None => return false,
+    };
+
+    for event in watcher.events.try_iter() {
+        match event {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Rename(_, path) => {
+                watcher.modified.insert(path);
+            }
+            _ => {}
+        }
+    }
+    watcher.modified.remove(file)
+}
@@ -1,12 +1,17 @@
+use std::cell::Cell;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
-use crate::asset::{self, audio::Samples};
+use crate::asset::{self, audio::{OggStream, Samples, CHANNELS}};
 use crate::random::{self, Distribute};
 
 use luminance_sdl2::sdl2::Sdl;
+use rustfft::{num_complex::Complex, FftPlanner};
 use luminance_sdl2::sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
 
 pub const SAMPLE_RATE: i32 = 48000;
+/// In frames, about 21 ms at [SAMPLE_RATE]. See [Audio::init_with].
+pub const DEFAULT_BUFFER_SIZE: u16 = 1024;
 
 macro_rules! impl_builder {
     ( $( $field:ident : $type:ty ),* $(,)? ) => {
@@ -19,20 +24,72 @@ macro_rules! impl_builder {
     }
 }
 
+/// Refers to a source that has been played, see [Audio::play].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SoundHandle(usize);
+
+/// A file that's decoded while it's played.
+#[derive(Clone)]
+struct Stream {
+    ogg: OggStream,
+    /// The decoded frames that haven't been played yet, starting at the
+    /// source's position.
+    buffer: Vec<f32>,
+    /// Frames that have been played and dropped from the buffer.
+    frames_played: usize,
+    /// Set when there's nothing more to decode.
+    finished: bool,
+}
+
+impl Stream {
+    /// Drops the frames before `position` and decodes until there are at
+    /// least `frames` frames after it.
+    fn fill(&mut self, position: &mut f32, frames: usize, looping: bool) {
+        let played = (*position as usize).min(self.buffer.len() / CHANNELS);
+        self.buffer.drain(..played * CHANNELS);
+        *position -= played as f32;
+        self.frames_played += played;
+
+        while !self.finished && self.buffer.len() < (*position as usize + frames) * CHANNELS {
+            if !self.ogg.read_packet(&mut self.buffer) {
+                let restarted = looping && self.ogg.rewind() && self.ogg.read_packet(&mut self.buffer);
+                if !restarted {
+                    self.finished = true;
+                }
+            }
+        }
+    }
+}
+
 /// A sound that is playing or can be played.
 #[derive(Clone)]
 pub struct AudioSource {
-    /// Which specific sample we're currently on.
+    /// Set when the source starts playing.
+    handle: Option<SoundHandle>,
+    /// Which frame we're currently on, a frame has a sample for each channel.
     position: f32,
     /// Whether we should loop when the sample is done.
     looping: bool,
-    /// The actual samples.
+    /// Whether we should play the samples backwards, starting from the end.
+    reversed: bool,
+    /// The actual samples. Empty for streams.
     samples: Arc<RwLock<Samples>>,
+    /// Set if the samples are decoded while playing, see [AudioSource::stream].
+    stream: Option<Stream>,
 
     gain: f32,
     gain_variance: f32,
     pitch: f32,
     pitch_variance: f32,
+    /// -1 is only the left speaker, 1 only the right.
+    pan: f32,
+    /// For [AudioSource::spatialize], in world units.
+    half_gain_distance: f32,
+    /// For [AudioSource::spatialize], in world units per second.
+    speed_of_sound: f32,
+    /// Sources with lower priority are stopped first when there are
+    /// too many playing, see [Audio::set_max_voices].
+    priority: u8,
 
     /// If we should remove this source when we get the opportunity.
     ///
@@ -45,48 +102,340 @@ pub struct AudioSource {
 impl AudioSource {
     pub fn new(audio: &asset::Audio) -> Self {
         Self {
+            handle: None,
             position: 0.0,
             looping: false,
+            reversed: false,
             samples: audio.samples(),
+            stream: None,
             gain: 1.0,
             gain_variance: 0.0,
             pitch: 1.0,
             pitch_variance: 0.0,
+            pan: 0.0,
+            half_gain_distance: 10.0,
+            speed_of_sound: 343.0,
+            priority: 0,
             remove: false,
         }
     }
 
     impl_builder!(
         looping: bool,
+        reversed: bool,
         gain: f32,
         gain_variance: f32,
         pitch: f32,
         pitch_variance: f32,
+        pan: f32,
+        half_gain_distance: f32,
+        speed_of_sound: f32,
+        priority: u8,
     );
+
+    /// Sets the gain in decibels, see [db_to_gain].
+    pub fn gain_db(self, db: f32) -> Self {
+        self.gain(db_to_gain(db))
+    }
+
+    /// Places the source in the world, relative to a listener. Sets the gain
+    /// from the distance, the pan from the direction and the pitch from the
+    /// velocity, so moving sources sound higher when they come closer.
+    ///
+    /// The gain is halved at [half_gain_distance](AudioSource::half_gain_distance),
+    /// 10 units by default. The doppler shift uses
+    /// [speed_of_sound](AudioSource::speed_of_sound), 343 units per second by default.
+    /// Overwrites the gain, pan and pitch.
+    pub fn spatialize(&mut self, listener: (f32, f32), source: (f32, f32), source_vel: (f32, f32)) {
+        let (dx, dy) = (source.0 - listener.0, source.1 - listener.1);
+        let distance = (dx * dx + dy * dy).sqrt();
+
+        self.gain = self.half_gain_distance / (self.half_gain_distance + distance);
+        // Close sources move towards the middle, instead of jumping between the speakers.
+        self.pan = (dx / distance.max(self.half_gain_distance)).max(-1.0).min(1.0);
+
+        // Positive when moving away from the listener.
+        let radial = if distance > 0.0 {
+            (source_vel.0 * dx + source_vel.1 * dy) / distance
+        } else {
+            0.0
+        };
+        // Sources at the speed of sound would have an infinite pitch.
+        let radial = radial.max(-0.9 * self.speed_of_sound);
+        self.pitch = self.speed_of_sound / (self.speed_of_sound + radial);
+    }
+
+    /// Plays an OGG file by decoding it a little at a time, instead of
+    /// loading it all into memory first. Good for long music tracks.
+    ///
+    /// Streams can't be reversed. Cloning a stream opens the file again when
+    /// the clone starts playing, and the clone is silent if that fails.
+    pub fn stream(file: PathBuf) -> Option<Self> {
+        let ogg = OggStream::open(file)?;
+        let samples = Samples::new(Vec::new(), ogg.sample_rate());
+        Some(Self {
+            handle: None,
+            position: 0.0,
+            looping: false,
+            reversed: false,
+            samples: Arc::new(RwLock::new(samples)),
+            stream: Some(Stream {
+                ogg,
+                buffer: Vec::new(),
+                frames_played: 0,
+                finished: false,
+            }),
+            gain: 1.0,
+            gain_variance: 0.0,
+            pitch: 1.0,
+            pitch_variance: 0.0,
+            pan: 0.0,
+            half_gain_distance: 10.0,
+            speed_of_sound: 343.0,
+            priority: 0,
+            remove: false,
+        })
+    }
+
+    /// How long the source plays for in seconds, taking pitch into account.
+    ///
+    /// Streams don't know how long they are, and return infinity.
+    pub fn duration(&self) -> f32 {
+        if self.stream.is_some() {
+            return f32::INFINITY;
+        }
+        self.samples.read().unwrap().duration() / self.pitch
+    }
+
+    /// How far into the sound the source is in seconds, taking pitch into account.
+    fn elapsed(&self) -> f32 {
+        let samples = self.samples.read().unwrap();
+        if let Some(stream) = &self.stream {
+            let position = stream.frames_played as f32 + self.position;
+            return position / (samples.sample_rate() as f32 * self.pitch);
+        }
+        let position = if self.reversed {
+            samples.num_frames() as f32 - 1.0 - self.position
+        } else {
+            self.position
+        };
+        position / (samples.sample_rate() as f32 * self.pitch)
+    }
+}
+
+/// Converts decibels to a linear gain. 0 dB is a gain of 1, and every
+/// -6 dB roughly halves it. Volume sliders feel more natural in decibels.
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// The inverse of [db_to_gain]. A gain of 0 is negative infinity.
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * gain.log10()
+}
+
+/// How loud each of `bands` frequency bands are in the samples, roughly
+/// between 0 and 1. The bands go from low to high, spaced logarithmically
+/// like we hear them. Great for drawing spectrum bars.
+///
+/// The samples should be mono, so average the channels of each frame first.
+/// A couple of thousand samples around the current position works well,
+/// more gives finer bands but reacts slower.
+pub fn spectrum(samples: &[f32], bands: usize) -> Vec<f32> {
+    let n = samples.len();
+    let mut levels = vec![0.0; bands];
+    if n < 4 || bands == 0 {
+        return levels;
+    }
+
+    // A Hann window, so the edges of the window don't show up as noise.
+    let mut buffer: Vec<_> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let w = 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (n - 1) as f32).cos();
+            Complex::new(x * w, 0.0)
+        })
+        .collect();
+    FftPlanner::<f32>::new().plan_fft_forward(n).process(&mut buffer);
+
+    // Only the first half are unique frequencies. A full scale sine ends
+    // up as n / 4, with the window.
+    let bins = n / 2;
+    let scale = 4.0 / n as f32;
+    for (band, level) in levels.iter_mut().enumerate() {
+        // Bin 0 is the average, which isn't interesting.
+        let edge = |b: usize| (bins as f32).powf(b as f32 / bands as f32) as usize;
+        let lo = edge(band).max(1);
+        let hi = edge(band + 1).max(lo + 1).min(bins);
+        *level = buffer[lo.min(hi)..hi]
+            .iter()
+            .fold(0.0, |max: f32, c| max.max(c.norm() * scale))
+            .min(1.0);
+    }
+    levels
+}
+
+/// Several variants of the same sound, like footsteps, where a random one
+/// is played each time so it doesn't get repetitive.
+#[derive(Clone)]
+pub struct SoundBank {
+    sources: Vec<AudioSource>,
+    /// Never play the same variant twice in a row. On by default.
+    pub avoid_repeats: bool,
+    last: Cell<Option<usize>>,
+}
+
+impl SoundBank {
+    /// # Panics
+    ///
+    /// Panics if there are no sounds.
+    pub fn new<'a>(audio: impl IntoIterator<Item = &'a asset::Audio>) -> Self {
+        Self::from_sources(audio.into_iter().map(AudioSource::new).collect())
+    }
+
+    /// Like [SoundBank::new], but every variant can have its own gain, pitch and so on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no sources.
+    pub fn from_sources(sources: Vec<AudioSource>) -> Self {
+        assert!(!sources.is_empty(), "a sound bank needs at least one sound");
+        Self {
+            sources,
+            avoid_repeats: true,
+            last: Cell::new(None),
+        }
+    }
+
+    pub fn sources(&self) -> &[AudioSource] {
+        &self.sources
+    }
+
+    /// Plays one of the variants, picked at random.
+    pub fn play_random(&self, audio: &mut Audio) -> SoundHandle {
+        let len = self.sources.len();
+        let pick = |n: usize| ((random::Uniform.sample() * n as f32) as usize).min(n - 1);
+        let i = match self.last.get() {
+            Some(last) if self.avoid_repeats && len > 1 => {
+                // Skip over the last variant.
+                let i = pick(len - 1);
+                if i >= last { i + 1 } else { i }
+            }
+            _ => pick(len),
+        };
+        self.last.set(Some(i));
+        audio.play(self.sources[i].clone())
+    }
 }
 
 /// The audio subsystem.
 pub struct Audio {
     sources: Vec<AudioSource>,
     gain: f32,
+    paused: bool,
+    next_handle: usize,
+    max_voices: Option<usize>,
+    limiter: bool,
+
+    /// The number of channels the device gave us.
+    channels: usize,
+    /// The sample rate the device gave us.
+    sample_rate: i32,
+    /// The buffer size the device gave us, in frames.
+    buffer_size: usize,
+
+    /// The loudest sample in the last buffer.
+    peak: f32,
+    /// The root mean square of the last buffer.
+    rms: f32,
 }
 
 impl Audio {
+    /// Opens the default device in stereo.
     pub fn init(sdl: &Sdl) -> AudioDevice<Self> {
-        let audio_subsystem = sdl.audio().unwrap();
+        Self::init_with_channels(sdl, 2)
+    }
+
+    /// Opens the default device, asking for the number of channels.
+    /// The device might give us something else, which is handled by
+    /// averaging to mono or leaving extra channels silent.
+    pub fn init_with_channels(sdl: &Sdl, channels: u8) -> AudioDevice<Self> {
+        Self::init_with(sdl, channels, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Opens the default device, asking for the number of channels and the
+    /// size of the buffer in frames. The size should be a power of two.
+    ///
+    /// A sound can't start until the current buffer has been played, so a
+    /// smaller buffer means less latency, which rhythm games want. But the
+    /// buffer is filled more often, and if it isn't filled in time the
+    /// sound crackles. Games that only play music can use a larger buffer.
+    ///
+    /// To use it with [Game](crate::Game), replace [Game::audio](crate::Game::audio).
+    pub fn init_with(sdl: &Sdl, channels: u8, buffer_size: u16) -> AudioDevice<Self> {
+        Self::try_init_with(sdl, channels, buffer_size).unwrap()
+    }
+
+    /// Like [Audio::init_with], but returns SDL's error instead of panicking
+    /// if there's no audio device.
+    pub fn try_init_with(sdl: &Sdl, channels: u8, buffer_size: u16) -> Result<AudioDevice<Self>, String> {
+        let audio_subsystem = sdl.audio()?;
         let desired = AudioSpecDesired {
             freq: Some(SAMPLE_RATE),
-            channels: Some(2),
-            samples: Some(1024),
+            channels: Some(channels),
+            samples: Some(buffer_size),
         };
 
         audio_subsystem.open_playback(None, &desired, |spec| {
-            assert_eq!(spec.freq, SAMPLE_RATE); //TODO handle differing sample rates gracefully
             Self {
                 sources: Vec::new(),
                 gain: 1.0,
+                paused: false,
+                next_handle: 0,
+                max_voices: None,
+                limiter: false,
+
+                channels: spec.channels.max(1) as usize,
+                sample_rate: spec.freq,
+                buffer_size: spec.samples as usize,
+
+                peak: 0.0,
+                rms: 0.0,
             }
-        }).unwrap()
+        })
+    }
+
+    /// The number of output channels.
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// The output sample rate, usually [SAMPLE_RATE].
+    pub fn sample_rate(&self) -> i32 {
+        self.sample_rate
+    }
+
+    /// The size of the device's buffer in frames, which might not be what was asked for.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// The latency added by the buffer, in seconds.
+    pub fn latency(&self) -> f32 {
+        self.buffer_size as f32 / self.sample_rate as f32
+    }
+
+    /// The loudest sample in the last mixed buffer, usually between 0 and 1.
+    /// Great for making things pulse to the music.
+    pub fn output_level(&self) -> f32 {
+        self.peak
+    }
+
+    /// The root mean square of the last mixed buffer. Follows how loud
+    /// the sound is perceived more smoothly than [Audio::output_level].
+    pub fn output_rms(&self) -> f32 {
+        self.rms
     }
 
     /// Start playing a new source.
@@ -96,8 +445,9 @@ impl Audio {
     ///
     /// # Panics
     ///
-    /// Panics if pitch <= 0.0 after applying pitch variance.
-    pub fn play(&mut self, mut source: AudioSource) {
+    /// Panics if pitch <= 0.0 after applying pitch variance, or if a
+    /// [stream](AudioSource::stream) is reversed.
+    pub fn play(&mut self, mut source: AudioSource) -> SoundHandle {
         if source.gain_variance != 0.0 {
             source.gain += random::Uniform.between(-source.gain_variance, source.gain_variance);
         }
@@ -105,7 +455,47 @@ impl Audio {
             source.pitch += random::Uniform.between(-source.pitch_variance, source.pitch_variance);
         }
         assert!(source.pitch > 0.0);
+        assert!(!(source.reversed && source.stream.is_some()), "streams can't be reversed");
+        if source.reversed {
+            source.position = source.samples.read().unwrap().num_frames() as f32 - 1.0;
+        }
+        let handle = SoundHandle(self.next_handle);
+        self.next_handle += 1;
+        source.handle = Some(handle);
         self.sources.push(source);
+
+        if let Some(max_voices) = self.max_voices {
+            while self.sources.len() > max_voices {
+                // The oldest of the lowest priority, which might be the new source.
+                let (i, _) = self.sources
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, s)| s.priority)
+                    .unwrap();
+                self.sources.remove(i);
+            }
+        }
+        handle
+    }
+
+    pub fn max_voices(&self) -> Option<usize> {
+        self.max_voices
+    }
+
+    /// Limits how many sources can play at once. When a new source is
+    /// played and there are too many, the oldest source with the lowest
+    /// [priority](AudioSource::priority) is stopped.
+    pub fn set_max_voices(&mut self, max_voices: Option<usize>) {
+        self.max_voices = max_voices;
+    }
+
+    /// How many seconds the sound has been playing for, or None if it
+    /// has stopped.
+    pub fn position(&self, handle: SoundHandle) -> Option<f32> {
+        self.sources
+            .iter()
+            .find(|s| s.handle == Some(handle))
+            .map(|s| s.elapsed())
     }
 
     pub fn gain(&self) -> f32 {
@@ -115,6 +505,35 @@ impl Audio {
     pub fn gain_mut(&mut self) -> &mut f32 {
         &mut self.gain
     }
+
+    /// The master gain in decibels, see [db_to_gain].
+    pub fn master_db(&self) -> f32 {
+        gain_to_db(self.gain)
+    }
+
+    pub fn set_master_db(&mut self, db: f32) {
+        self.gain = db_to_gain(db);
+    }
+
+    pub fn limiter(&self) -> bool {
+        self.limiter
+    }
+
+    /// Soft clips the final mix, so loud scenes distort smoothly instead
+    /// of crackling. Quiet sounds are barely affected.
+    pub fn set_limiter(&mut self, limiter: bool) {
+        self.limiter = limiter;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Stops all sources where they are. Unlike setting the gain to zero,
+    /// the sources don't move forward while paused.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
 }
 
 impl AudioCallback for Audio {
@@ -126,36 +545,93 @@ impl AudioCallback for Audio {
             *x = 0.0;
         }
 
+        if self.paused {
+            self.peak = 0.0;
+            self.rms = 0.0;
+            return;
+        }
+
+        let channels = self.channels;
         'sources: for source in self.sources.iter_mut() {
             let samples = source.samples.read().unwrap();
-            for x in out.iter_mut() {
-                // Move forward
-                source.position += source.pitch * samples.sample_rate() as f32 / SAMPLE_RATE as f32 ;
+            let volume = source.gain * self.gain;
+            // How far to move for each output frame.
+            let step = source.pitch * samples.sample_rate() as f32 / self.sample_rate as f32;
+
+            // Streams loop by decoding the file again, so the buffer never loops.
+            let (data, looping) = match &mut source.stream {
+                Some(stream) => {
+                    let frames = (step * (out.len() / channels) as f32).ceil() as usize + 2;
+                    stream.fill(&mut source.position, frames, source.looping);
+                    (&stream.buffer[..], false)
+                }
+                None => (samples.data(), source.looping),
+            };
+            let num_frames = data.len() / CHANNELS;
+
+            for out_frame in out.chunks_exact_mut(channels) {
+                // Move forward, or backward if reversed
+                if source.reversed {
+                    source.position -= step;
+                } else {
+                    source.position += step;
+                }
+                if looping && num_frames != 0 {
+                    source.position = source.position.rem_euclid(num_frames as f32);
+                    // Rounding can give exactly num_frames for tiny negative positions.
+                    if source.position >= num_frames as f32 {
+                        source.position = 0.0;
+                    }
+                }
                 let position = source.position as usize; // Truncates
-                let data = samples.data();
-                let num_samples = data.len();
 
                 // Check if we're done
-                if num_samples <= position && !source.looping {
+                if source.position < 0.0 || num_frames <= position {
                     source.remove = true;
                     continue 'sources;
                 }
 
-                let (a, b) = if source.looping {
-                    // Keep the decimal on source.position
-                    source.position -= (source.position as usize - position) as f32;
-                    (&data[position % num_samples], &data[(position + 1) % num_samples])
+                let next = if looping {
+                    (position + 1) % num_frames
                 } else {
-                    (data.get(position).unwrap_or(&0.0), data.get(position + 1).unwrap_or(&0.0))
+                    position + 1
                 };
+                // Interpolate linearly between the two closest frames, so
+                // pitched sounds don't get gritty.
+                let fade = source.position.fract();
+                let mut frame = [0.0; CHANNELS];
+                for (channel, sample) in frame.iter_mut().enumerate() {
+                    let a = data[position * CHANNELS + channel];
+                    let b = data.get(next * CHANNELS + channel).copied().unwrap_or(0.0);
+                    *sample = ((1.0 - fade) * a + fade * b) * volume;
+                }
+                frame[0] *= (1.0 - source.pan).min(1.0);
+                frame[1] *= (1.0 + source.pan).min(1.0);
+
+                // Write data, the samples are always stereo.
+                if let [mono] = out_frame {
+                    *mono += frame.iter().sum::<f32>() / CHANNELS as f32;
+                } else {
+                    for (x, sample) in out_frame.iter_mut().zip(frame.iter()) {
+                        *x += sample;
+                    }
+                }
+            }
+        }
 
-                // Write data
-                let fade = source.position.rem_euclid(1.0);
-                *x += (1.0 - fade) * a * source.gain * self.gain;
-                *x +=       (fade) * b * source.gain * self.gain;
+        if self.limiter {
+            for x in out.iter_mut() {
+                *x = x.tanh();
             }
         }
 
+        self.peak = out.iter().fold(0.0, |peak, x| x.abs().max(peak));
+        self.rms = if out.is_empty() {
+            0.0
+        } else {
+            (out.iter().map(|x| x * x).sum::<f32>() / out.len() as f32).sqrt()
+        };
+
         // Remove sources that have finished.
         let mut i = 0;
         while i != self.sources.len() {
@@ -167,3 +643,37 @@ impl AudioCallback for Audio {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spectrum_of_too_few_samples() {
+        assert_eq!(spectrum(&[0.5; 3], 4), vec![0.0; 4]);
+        assert!(spectrum(&[0.5; 64], 0).is_empty());
+    }
+
+    #[test]
+    fn spectrum_of_silence() {
+        assert_eq!(spectrum(&[0.0; 256], 8), vec![0.0; 8]);
+    }
+
+    #[test]
+    fn spectrum_finds_a_sine() {
+        // 64 periods over 1024 samples lands in bin 64, which is band 5 of 8.
+        let n = 1024;
+        let sine: Vec<f32> = (0..n)
+            .map(|i| (2.0 * std::f32::consts::PI * 64.0 * i as f32 / n as f32).sin())
+            .collect();
+        let levels = spectrum(&sine, 8);
+        assert_eq!(levels.len(), 8);
+        for (band, level) in levels.iter().enumerate() {
+            if band == 5 {
+                assert!(*level > 0.9, "band {} is {}", band, level);
+            } else {
+                assert!(*level < 0.1, "band {} is {}", band, level);
+            }
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Simple 2D shapes, and checks for when they touch.
+//!
+//! ```ignore
+//! let player = Aabb::from_rect(&player_rect);
+//! let coin = Circle::new(Vector2::new(1.0, 0.5), 0.2);
+//! if player.overlaps(&coin) {
+//!     pick_up_coin();
+//! }
+//! ```
+//!
+//! For lots of shapes, a [SpatialHash] finds what's nearby without
+//! checking everything.
+
+use crate::renderer::{Rect, Sprite};
+
+use cgmath::Vector2;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub center: Vector2<f32>,
+    /// Half the width and half the height.
+    pub half_extents: Vector2<f32>,
+}
+
+impl Aabb {
+    pub fn new(center: Vector2<f32>, half_extents: Vector2<f32>) -> Self {
+        Self { center, half_extents }
+    }
+
+    /// The box covered by the rect. Rotation is ignored.
+    pub fn from_rect(rect: &Rect) -> Self {
+        Self::new(rect.position, rect.scale / 2.0)
+    }
+
+    /// The box covered by the sprite. Rotation is ignored.
+    pub fn from_sprite(sprite: &Sprite) -> Self {
+        Self::new(sprite.position, sprite.scale / 2.0)
+    }
+
+    pub fn min(&self) -> Vector2<f32> {
+        self.center - self.half_extents
+    }
+
+    pub fn max(&self) -> Vector2<f32> {
+        self.center + self.half_extents
+    }
+
+    pub fn contains_point(&self, point: Vector2<f32>) -> bool {
+        let (min, max) = (self.min(), self.max());
+        min.x <= point.x && point.x <= max.x && min.y <= point.y && point.y <= max.y
+    }
+
+    /// The point in the box that is closest to the given point.
+    pub fn closest_point(&self, point: Vector2<f32>) -> Vector2<f32> {
+        let (min, max) = (self.min(), self.max());
+        Vector2::new(point.x.max(min.x).min(max.x), point.y.max(min.y).min(max.y))
+    }
+}
+
+/// A circle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Circle {
+    pub center: Vector2<f32>,
+    pub radius: f32,
+}
+
+impl Circle {
+    pub fn new(center: Vector2<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+
+    pub fn contains_point(&self, point: Vector2<f32>) -> bool {
+        distance_squared(self.center, point) <= self.radius * self.radius
+    }
+}
+
+/// Shapes that can check if they overlap other shapes.
+pub trait Overlaps<T> {
+    /// True if the shapes share at least one point.
+    fn overlaps(&self, other: &T) -> bool;
+}
+
+impl Overlaps<Aabb> for Aabb {
+    fn overlaps(&self, other: &Aabb) -> bool {
+        let d = self.center - other.center;
+        let e = self.half_extents + other.half_extents;
+        d.x.abs() <= e.x && d.y.abs() <= e.y
+    }
+}
+
+impl Overlaps<Circle> for Circle {
+    fn overlaps(&self, other: &Circle) -> bool {
+        let r = self.radius + other.radius;
+        distance_squared(self.center, other.center) <= r * r
+    }
+}
+
+impl Overlaps<Circle> for Aabb {
+    fn overlaps(&self, other: &Circle) -> bool {
+        other.contains_point(self.closest_point(other.center))
+    }
+}
+
+impl Overlaps<Aabb> for Circle {
+    fn overlaps(&self, other: &Aabb) -> bool {
+        other.overlaps(self)
+    }
+}
+
+fn distance_squared(a: Vector2<f32>, b: Vector2<f32>) -> f32 {
+    let d = a - b;
+    d.x * d.x + d.y * d.y
+}
+
+type Cell = (i32, i32);
+
+/// Splits the world into a uniform grid, to quickly find boxes in a region.
+///
+/// Either [clear](SpatialHash::clear) it and insert everything each frame,
+/// or [update](SpatialHash::update) the boxes that moved.
+pub struct SpatialHash<Id> {
+    cell_size: f32,
+    cells: HashMap<Cell, Vec<Id>>,
+    boxes: HashMap<Id, Aabb>,
+}
+
+impl<Id: Copy + Eq + Hash> SpatialHash<Id> {
+    /// The cell size should be around the size of a typical box.
+    pub fn new(cell_size: f32) -> Self {
+        assert!(cell_size > 0.0);
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            boxes: HashMap::new(),
+        }
+    }
+
+    fn cells(&self, aabb: &Aabb) -> impl Iterator<Item = Cell> {
+        let cell = |x: f32| (x / self.cell_size).floor() as i32;
+        let (min, max) = (aabb.min(), aabb.max());
+        let (x0, y0, x1, y1) = (cell(min.x), cell(min.y), cell(max.x), cell(max.y));
+        (x0..=x1).flat_map(move |x| (y0..=y1).map(move |y| (x, y)))
+    }
+
+    /// Adds a box. Replaces the old box if the id is already used.
+    pub fn insert(&mut self, id: Id, aabb: Aabb) {
+        self.remove(id);
+        for cell in self.cells(&aabb) {
+            self.cells.entry(cell).or_insert_with(Vec::new).push(id);
+        }
+        self.boxes.insert(id, aabb);
+    }
+
+    /// Moves a box, same as [SpatialHash::insert].
+    pub fn update(&mut self, id: Id, aabb: Aabb) {
+        self.insert(id, aabb);
+    }
+
+    /// Removes a box, returning it if it was there.
+    pub fn remove(&mut self, id: Id) -> Option<Aabb> {
+        let aabb = self.boxes.remove(&id)?;
+        for cell in self.cells(&aabb) {
+            if let Some(ids) = self.cells.get_mut(&cell) {
+                ids.retain(|other| *other != id);
+                if ids.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+        Some(aabb)
+    }
+
+    /// Removes all boxes.
+    pub fn clear(&mut self) {
+        self.cells.clear();
+        self.boxes.clear();
+    }
+
+    pub fn get(&self, id: Id) -> Option<&Aabb> {
+        self.boxes.get(&id)
+    }
+
+    /// All boxes that overlap the region.
+    pub fn query(&self, region: &Aabb) -> Vec<Id> {
+        let mut seen = HashSet::new();
+        self.cells(region)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(|id| seen.insert(*id) && self.boxes[id].overlaps(region))
+            .collect()
+    }
+
+    /// All boxes that contain the point.
+    pub fn query_point(&self, point: Vector2<f32>) -> Vec<Id> {
+        self.query(&Aabb::new(point, Vector2::new(0.0, 0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn aabb(x: f32, y: f32, hw: f32, hh: f32) -> Aabb {
+        Aabb::new(Vector2::new(x, y), Vector2::new(hw, hh))
+    }
+
+    #[test]
+    fn aabbs_that_touch_overlap() {
+        let a = aabb(0.0, 0.0, 1.0, 1.0);
+        assert!(a.overlaps(&aabb(2.0, 0.0, 1.0, 1.0)));
+        assert!(a.overlaps(&aabb(2.0, 2.0, 1.0, 1.0)));
+        assert!(!a.overlaps(&aabb(2.5, 0.0, 1.0, 1.0)));
+        assert!(!a.overlaps(&aabb(0.0, -2.5, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn aabb_contains_its_edges() {
+        let a = aabb(1.0, 1.0, 1.0, 0.5);
+        assert!(a.contains_point(Vector2::new(0.0, 0.5)));
+        assert!(a.contains_point(Vector2::new(2.0, 1.5)));
+        assert!(!a.contains_point(Vector2::new(2.0, 1.75)));
+    }
+
+    #[test]
+    fn closest_point_is_clamped() {
+        let a = aabb(0.0, 0.0, 1.0, 1.0);
+        assert_eq!(a.closest_point(Vector2::new(3.0, 0.5)), Vector2::new(1.0, 0.5));
+        assert_eq!(a.closest_point(Vector2::new(-3.0, -3.0)), Vector2::new(-1.0, -1.0));
+        assert_eq!(a.closest_point(Vector2::new(0.5, 0.25)), Vector2::new(0.5, 0.25));
+    }
+
+    #[test]
+    fn circles_that_touch_overlap() {
+        let a = Circle::new(Vector2::new(0.0, 0.0), 1.0);
+        assert!(a.overlaps(&Circle::new(Vector2::new(3.0, 0.0), 2.0)));
+        assert!(!a.overlaps(&Circle::new(Vector2::new(3.0, 0.0), 1.5)));
+    }
+
+    #[test]
+    fn circle_and_aabb() {
+        let a = aabb(0.0, 0.0, 1.0, 1.0);
+        // Touching a side.
+        assert!(a.overlaps(&Circle::new(Vector2::new(2.0, 0.0), 1.0)));
+        assert!(Circle::new(Vector2::new(2.0, 0.0), 1.0).overlaps(&a));
+        // Near a corner, but not touching it.
+        assert!(!a.overlaps(&Circle::new(Vector2::new(2.0, 2.0), 1.0)));
+        // Inside the box.
+        assert!(a.overlaps(&Circle::new(Vector2::new(0.0, 0.0), 0.25)));
+    }
+
+    #[test]
+    fn spatial_hash_finds_boxes_at_negative_coordinates() {
+        let mut hash = SpatialHash::new(1.0);
+        hash.insert(1, aabb(-1.5, -1.5, 0.25, 0.25));
+        hash.insert(2, aabb(1.5, 1.5, 0.25, 0.25));
+        assert_eq!(hash.query_point(Vector2::new(-1.5, -1.5)), vec![1]);
+        assert_eq!(hash.query_point(Vector2::new(-0.5, -0.5)), Vec::<i32>::new());
+        assert_eq!(hash.query_point(Vector2::new(1.5, 1.5)), vec![2]);
+    }
+
+    #[test]
+    fn spatial_hash_returns_boxes_in_many_cells_once() {
+        let mut hash = SpatialHash::new(1.0);
+        hash.insert(1, aabb(0.0, 0.0, 2.0, 2.0));
+        assert_eq!(hash.query(&aabb(0.0, 0.0, 3.0, 3.0)), vec![1]);
+    }
+
+    #[test]
+    fn spatial_hash_only_returns_overlapping_boxes() {
+        let mut hash = SpatialHash::new(4.0);
+        // Same cell, but far enough apart not to overlap.
+        hash.insert(1, aabb(0.5, 0.5, 0.25, 0.25));
+        hash.insert(2, aabb(3.5, 3.5, 0.25, 0.25));
+        assert_eq!(hash.query(&aabb(0.5, 0.5, 0.5, 0.5)), vec![1]);
+    }
+
+    #[test]
+    fn spatial_hash_update_and_remove() {
+        let mut hash = SpatialHash::new(1.0);
+        hash.insert(1, aabb(0.5, 0.5, 0.25, 0.25));
+        hash.update(1, aabb(5.5, 5.5, 0.25, 0.25));
+        assert!(hash.query_point(Vector2::new(0.5, 0.5)).is_empty());
+        assert_eq!(hash.query_point(Vector2::new(5.5, 5.5)), vec![1]);
+
+        assert_eq!(hash.remove(1), Some(aabb(5.5, 5.5, 0.25, 0.25)));
+        assert_eq!(hash.remove(1), None);
+        assert!(hash.query_point(Vector2::new(5.5, 5.5)).is_empty());
+        assert!(hash.cells.is_empty());
+    }
+}
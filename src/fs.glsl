@@ -2,13 +2,24 @@ in vec4 v_color;
 in vec3 v_uv;
 
 uniform sampler3D tex;
+// Non-zero if textures and colors are sRGB and should be blended in linear space.
+uniform int linear_color;
 
 out vec4 frag_color;
 
+vec4 to_linear(vec4 c) {
+    if (linear_color == 0) {
+        return c;
+    }
+    vec3 lo = c.rgb / 12.92;
+    vec3 hi = pow((c.rgb + 0.055) / 1.055, vec3(2.4));
+    return vec4(mix(hi, lo, vec3(lessThanEqual(c.rgb, vec3(0.04045)))), c.a);
+}
+
 void main() {
     if (v_uv.z < 0) {
-        frag_color = v_color;
+        frag_color = to_linear(v_color);
     } else {
-        frag_color = (v_color * texture(tex, v_uv));
+        frag_color = (to_linear(v_color) * to_linear(texture(tex, v_uv)));
     }
 }
@@ -36,12 +36,14 @@ use luminance_sdl2::sdl2;
 use sdl2::{GameControllerSubsystem, Sdl};
 use sdl2::controller::GameController;
 use sdl2::event::{Event, WindowEvent};
-use std::collections::HashMap;
-use std::convert::TryInto;
+use sdl2::clipboard::ClipboardUtil;
+use sdl2::keyboard::{KeyboardUtil, TextInputUtil};
+use sdl2::mouse::MouseUtil;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
 /// All the different kinds of input devices we can listen to.
-#[derive(Hash, Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Hash, Clone, Debug, Eq, PartialEq)]
 pub enum Device {
     /// The magic quit event, when the window is closed.
     Quit,
@@ -49,6 +51,40 @@ pub enum Device {
     Mouse(MouseButton),
     Button(u32, Button),
     Axis(u32, Axis),
+    /// Down while all of the devices are down, like Ctrl+Z. It's pressed
+    /// when the last of them goes down. Combos can't contain combos.
+    Combo(Vec<Device>),
+}
+
+/// Which modifier keys are held down, left or right doesn't matter.
+/// See [InputManager::modifiers].
+#[derive(Hash, Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    /// The Windows, Command or Super key.
+    pub gui: bool,
+}
+
+impl From<Mod> for Modifiers {
+    fn from(keymod: Mod) -> Self {
+        Self {
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+            gui: keymod.intersects(Mod::LGUIMOD | Mod::RGUIMOD),
+        }
+    }
+}
+
+/// What's happened to a text field since the last update.
+#[derive(Clone, Debug)]
+enum TextEvent {
+    /// Text, as given by the keyboard layout or IME.
+    Text(String),
+    /// Editing keys, like backspace and the arrows.
+    Key(Keycode, Mod),
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -64,12 +100,35 @@ pub struct InputManager<T> {
     controllers: GameControllerSubsystem,
     physical_inputs: HashMap<Device, T>,
     virtual_inputs: HashMap<T, KeyState>,
+    /// Analog thresholds that aren't [TRIGGER_LIMIT].
+    thresholds: HashMap<T, f32>,
+    /// Up, down, left and right for each vector.
+    vectors: HashMap<T, [T; 4]>,
     opened_controllers: HashMap<u32, GameController>,
+    last_input_device: Option<Device>,
+    /// All devices that are down, bound or not. Used for combos.
+    held: HashSet<Device>,
+    /// The combos that were down after the last call to [InputManager::poll].
+    combos_down: HashSet<Device>,
+    keyboard: KeyboardUtil,
+    mouse_util: MouseUtil,
+    /// All mouse buttons, bound or not.
+    mouse_buttons: HashMap<MouseButton, KeyState>,
     mouse: [i32; 2],
     /// Since the last call to [InputManager::poll].
     mouse_rel: [i32; 2],
+    /// The new window size, if it changed since the last call to [InputManager::poll].
+    window_resized: Option<(u32, u32)>,
+    window_focused: bool,
+    mouse_in_window: bool,
+    clipboard: ClipboardUtil,
+    text_input: TextInputUtil,
     text_input_enabled: bool,
-    text_input_events: Vec<(Keycode, Mod)>,
+    text_input_events: Vec<TextEvent>,
+    /// In characters, not bytes.
+    text_cursor: usize,
+    /// Text the IME is still composing.
+    text_composition: String,
 }
 
 /// [i32::MIN, i32::MAX] -> [-1.0, 1.0)
@@ -86,7 +145,22 @@ fn remap(value: i16) -> f32 {
     }
 }
 
-/// When an analog signal becomes digital.
+/// [0, i16::MAX] -> [0.0, 1.0]
+///
+/// Triggers rest at 0 and only go one way, so they get their own mapping.
+fn remap_trigger(value: i16) -> f32 {
+    let value = (value.max(0) as f32) / (i16::MAX as f32);
+    // Arbitrarily chosen, triggers are less noisy than sticks.
+    const DEADZONE: f32 = 0.05;
+    if value < DEADZONE {
+        0.0
+    } else {
+        (value - DEADZONE) / (1.0 - DEADZONE)
+    }
+}
+
+/// When an analog signal becomes digital, unless the input has its own
+/// threshold. See [InputManager::set_trigger_threshold].
 const TRIGGER_LIMIT: f32 = 0.1;
 
 impl<T> InputManager<T>
@@ -94,20 +168,41 @@ where
     T: Clone + Hash + Eq,
 {
     pub fn new(sdl: &Sdl) -> Self {
-        let controllers = sdl.game_controller().unwrap();
+        Self::try_new(sdl).unwrap()
+    }
+
+    /// Like [InputManager::new], but returns SDL's error instead of panicking.
+    pub fn try_new(sdl: &Sdl) -> Result<Self, String> {
+        let controllers = sdl.game_controller()?;
         controllers.set_event_state(true);
+        let video = sdl.video()?;
 
-        Self {
+        Ok(Self {
             physical_inputs: HashMap::new(),
             virtual_inputs: HashMap::new(),
+            thresholds: HashMap::new(),
+            vectors: HashMap::new(),
             frame: 0,
             controllers: controllers.clone(),
             opened_controllers: HashMap::new(),
+            last_input_device: None,
+            held: HashSet::new(),
+            combos_down: HashSet::new(),
+            keyboard: sdl.keyboard(),
+            mouse_util: sdl.mouse(),
+            mouse_buttons: HashMap::new(),
             mouse: [0, 0],
             mouse_rel: [0, 0],
+            window_resized: None,
+            window_focused: true,
+            mouse_in_window: false,
+            clipboard: video.clipboard(),
+            text_input: video.text_input(),
             text_input_enabled: false,
             text_input_events: Vec::new(),
-        }
+            text_cursor: 0,
+            text_composition: String::new(),
+        })
     }
 
     /// Creates a new binding to listen to.
@@ -116,12 +211,62 @@ where
         self.virtual_inputs.insert(name, KeyState::Up(0));
     }
 
+    /// Combines four inputs into a vector, queried with [InputManager::vector].
+    /// An analog stick can be bound to just `right` and `up`.
+    pub fn bind_vector(&mut self, up: T, down: T, left: T, right: T, name: T) {
+        self.vectors.insert(name, [up, down, left, right]);
+    }
+
+    /// Returns the vector bound with [InputManager::bind_vector], with a length
+    /// of at most 1. Going diagonally isn't faster.
+    pub fn vector(&self, name: T) -> (f32, f32) {
+        match self.vectors.get(&name) {
+            Some([up, down, left, right]) => {
+                let x = self.value(right.clone()) - self.value(left.clone());
+                let y = self.value(up.clone()) - self.value(down.clone());
+                let length = (x * x + y * y).sqrt();
+                if length > 1.0 {
+                    (x / length, y / length)
+                } else {
+                    (x, y)
+                }
+            }
+            None => (0.0, 0.0),
+        }
+    }
+
+    /// How far an analog input has to move before [InputManager::down]
+    /// is true. Defaults to 0.1. Analog devices in a [Device::Combo] count
+    /// as held past the threshold of the combo.
+    pub fn set_trigger_threshold(&mut self, name: T, threshold: f32) {
+        self.thresholds.insert(name, threshold);
+    }
+
+    pub fn trigger_threshold(&self, name: T) -> f32 {
+        self.thresholds.get(&name).copied().unwrap_or(TRIGGER_LIMIT)
+    }
+
+    /// The threshold of the input the device is bound to, or of a combo
+    /// it's part of.
+    fn device_threshold(&self, device: &Device) -> f32 {
+        let name = self.physical_inputs.get(device).or_else(|| {
+            self.physical_inputs.iter().find_map(|(bound, name)| match bound {
+                Device::Combo(devices) if devices.contains(device) => Some(name),
+                _ => None,
+            })
+        });
+        match name {
+            Some(name) => self.trigger_threshold(name.clone()),
+            None => TRIGGER_LIMIT,
+        }
+    }
+
     /// Check if the input is down this frame.
     pub fn down(&self, name: T) -> bool {
         match self.virtual_inputs.get(&name) {
             Some(KeyState::Down(_)) => true,
             Some(KeyState::Up(_)) => false,
-            Some(KeyState::Analog(v)) => v.abs() > TRIGGER_LIMIT,
+            Some(KeyState::Analog(v)) => v.abs() > self.trigger_threshold(name),
             None => {
                 // TODO(ed): I don't like this... but it's here now.
                 false
@@ -134,7 +279,7 @@ where
         match self.virtual_inputs.get(&name) {
             Some(KeyState::Down(_)) => false,
             Some(KeyState::Up(_)) => true,
-            Some(KeyState::Analog(v)) => v.abs() < TRIGGER_LIMIT,
+            Some(KeyState::Analog(v)) => v.abs() < self.trigger_threshold(name),
             None => {
                 // TODO(ed): I don't like this... but it's here now.
                 false
@@ -164,6 +309,17 @@ where
         }
     }
 
+    /// Check if the input is pressed this frame while exactly these
+    /// modifiers are held. Ctrl+S doesn't fire for Ctrl+Shift+S.
+    pub fn pressed_with_mod(&self, name: T, modifiers: Modifiers) -> bool {
+        self.pressed(name) && self.modifiers() == modifiers
+    }
+
+    /// The modifier keys that are held down right now.
+    pub fn modifiers(&self) -> Modifiers {
+        self.keyboard.mod_state().into()
+    }
+
     /// Returns the inputs as analog signals.
     pub fn value(&self, name: T) -> f32 {
         match self.virtual_inputs.get(&name) {
@@ -191,6 +347,23 @@ where
         }
     }
 
+    /// The ids of all connected controllers, as used by [Device::Button] and [Device::Axis].
+    pub fn connected_controllers(&self) -> Vec<u32> {
+        let mut controllers: Vec<_> = self.opened_controllers.keys().copied().collect();
+        controllers.sort_unstable();
+        controllers
+    }
+
+    pub fn controller_name(&self, controller: u32) -> Option<String> {
+        self.opened_controllers.get(&controller).map(|c| c.name())
+    }
+
+    /// The device that was last pressed or moved, bound or not.
+    /// Useful for rebinding: wait for this to change and bind the new device.
+    pub fn last_input_device(&self) -> Option<Device> {
+        self.last_input_device.clone()
+    }
+
     /// Returns the current mouse position.
     pub fn mouse(&self) -> (i32, i32) {
         (self.mouse[0], self.mouse[1])
@@ -202,35 +375,173 @@ where
         (self.mouse_rel[0], self.mouse_rel[1])
     }
 
+    /// Check if the mouse button is down this frame, without binding it.
+    pub fn mouse_down(&self, button: MouseButton) -> bool {
+        matches!(self.mouse_buttons.get(&button), Some(KeyState::Down(_)))
+    }
+
+    /// Check if the mouse button is pressed this frame, without binding it.
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool {
+        matches!(self.mouse_buttons.get(&button), Some(KeyState::Down(frame)) if *frame == self.frame)
+    }
+
+    /// Check if the mouse button was released this frame, without binding it.
+    pub fn mouse_released(&self, button: MouseButton) -> bool {
+        matches!(self.mouse_buttons.get(&button), Some(KeyState::Up(frame)) if *frame == self.frame)
+    }
+
+    /// Hides the cursor and locks it to the window, for things like mouse-look.
+    /// The mouse position stops changing, use [InputManager::mouse_rel] instead.
+    pub fn set_relative_mouse(&mut self, relative: bool) {
+        self.mouse_util.set_relative_mouse_mode(relative);
+    }
+
+    pub fn relative_mouse(&self) -> bool {
+        self.mouse_util.relative_mouse_mode()
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        self.mouse_util.show_cursor(visible);
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.mouse_util.is_cursor_showing()
+    }
+
+    /// Returns the new size of the window if it was resized since the last
+    /// call to [InputManager::poll].
+    pub fn window_resized(&self) -> Option<(u32, u32)> {
+        self.window_resized
+    }
+
+    /// False when another window has the keyboard focus. Pausing and
+    /// muting then is common courtesy.
+    pub fn window_focused(&self) -> bool {
+        self.window_focused
+    }
+
+    /// Whether the mouse is over the window.
+    pub fn mouse_in_window(&self) -> bool {
+        self.mouse_in_window
+    }
+
+    /// Returns the text on the clipboard, if there is any.
+    pub fn clipboard_text(&self) -> Option<String> {
+        if self.clipboard.has_clipboard_text() {
+            self.clipboard.clipboard_text().ok()
+        } else {
+            None
+        }
+    }
+
+    pub fn set_clipboard_text(&mut self, text: &str) -> Result<(), ()> {
+        self.clipboard.set_clipboard_text(text).map_err(|_| ())
+    }
+
+    /// While text input is enabled key presses go to
+    /// [InputManager::text_input_update] instead of the bindings.
     pub fn set_text_input_enabled(&mut self, enabled: bool) {
         self.text_input_enabled = enabled;
+        if enabled {
+            self.text_input.start();
+        } else {
+            self.text_input.stop();
+            self.text_composition.clear();
+        }
+    }
+
+    /// Where new text is inserted, in characters from the start of the string.
+    pub fn text_cursor(&self) -> usize {
+        self.text_cursor
+    }
+
+    pub fn set_text_cursor(&mut self, cursor: usize) {
+        self.text_cursor = cursor;
+    }
+
+    /// Text that's being composed by an IME but isn't committed yet.
+    /// Draw it at the cursor.
+    pub fn text_composition(&self) -> &str {
+        &self.text_composition
     }
 
+    /// Applies the text input since the last call to `s`. Returns true if
+    /// return or escape was pressed. Escape also clears the string and
+    /// Ctrl+V pastes at the cursor.
+    ///
+    /// The text comes from SDL's text events, not from the keycodes, so it
+    /// follows the user's keyboard layout and IME. AZERTY gives an 'a' where
+    /// QWERTY gives a 'q'.
     pub fn text_input_update(&mut self, s: &mut String) -> bool {
+        // The string might have changed since last time.
+        let mut cursor = self.text_cursor.min(s.chars().count());
+        let byte_index = |s: &String, cursor: usize| {
+            s.char_indices().nth(cursor).map_or(s.len(), |(i, _)| i)
+        };
+
         let mut found_return = false;
-        for (keycode, keymod) in std::mem::take(&mut self.text_input_events) {
-            match keycode {
-                Keycode::Backspace => { s.pop(); }
-                Keycode::Escape => { s.clear(); found_return = true; }
-                Keycode::Return => found_return = true,
-                c => if let Some(mut c) = (c as i32).try_into().ok().and_then(char::from_u32) {
-                    //TODO(gu): Handle non-ascii better.
-                    if c.is_ascii_alphanumeric() || c.is_ascii_punctuation() || c == ' ' {
-                        if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
-                            c.make_ascii_uppercase();
-                        }
-                        s.push(c);
+        for event in std::mem::take(&mut self.text_input_events) {
+            match event {
+                TextEvent::Text(text) => {
+                    s.insert_str(byte_index(s, cursor), &text);
+                    cursor += text.chars().count();
+                }
+                TextEvent::Key(Keycode::V, keymod)
+                    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    if let Some(text) = self.clipboard_text() {
+                        s.insert_str(byte_index(s, cursor), &text);
+                        cursor += text.chars().count();
                     }
+                }
+                TextEvent::Key(keycode, _) => match keycode {
+                    Keycode::Backspace => if cursor > 0 {
+                        cursor -= 1;
+                        s.remove(byte_index(s, cursor));
+                    },
+                    Keycode::Delete => if cursor < s.chars().count() {
+                        s.remove(byte_index(s, cursor));
+                    },
+                    Keycode::Left => cursor = cursor.saturating_sub(1),
+                    Keycode::Right => cursor = (cursor + 1).min(s.chars().count()),
+                    Keycode::Home => cursor = 0,
+                    Keycode::End => cursor = s.chars().count(),
+                    Keycode::Escape => { s.clear(); cursor = 0; found_return = true; }
+                    Keycode::Return | Keycode::KpEnter => found_return = true,
+                    _ => {}
                 },
             }
         }
+        self.text_cursor = cursor;
         found_return
     }
 
+    /// Releases every input without unbinding anything, so a key that's held
+    /// when switching from gameplay to a menu doesn't trigger something there.
+    /// Inputs that are still held stay up until they're pressed again, and
+    /// aren't [released](InputManager::released) when they're let go.
+    ///
+    /// Also throws away text input and mouse movement that hasn't been used.
+    pub fn reset(&mut self) {
+        // Like a new binding, so nothing is released this frame either.
+        for state in self.virtual_inputs.values_mut() {
+            *state = KeyState::Up(0);
+        }
+        for state in self.mouse_buttons.values_mut() {
+            *state = KeyState::Up(0);
+        }
+        self.held.clear();
+        self.combos_down.clear();
+        self.text_input_events.clear();
+        self.text_composition.clear();
+        self.mouse_rel = [0, 0];
+    }
+
     /// Update the state of the input.
     pub fn poll(&mut self, sdl: &sdl2::Sdl) {
         self.frame += 1;
         self.mouse_rel = [0, 0];
+        self.window_resized = None;
         let frame = self.frame;
         for event in sdl.event_pump().unwrap().poll_iter() {
             let (input, down) = match event {
@@ -239,21 +550,56 @@ where
                     win_event: WindowEvent::Close,
                     ..
                 } => (Device::Quit, KeyState::Down(frame)),
+                Event::Window {
+                    win_event: WindowEvent::Resized(w, h),
+                    ..
+                }
+                | Event::Window {
+                    win_event: WindowEvent::SizeChanged(w, h),
+                    ..
+                } => {
+                    self.window_resized = Some((w as u32, h as u32));
+                    continue;
+                }
+                Event::Window { win_event, .. } => {
+                    match win_event {
+                        WindowEvent::FocusGained => self.window_focused = true,
+                        WindowEvent::FocusLost => self.window_focused = false,
+                        WindowEvent::Enter => self.mouse_in_window = true,
+                        WindowEvent::Leave => self.mouse_in_window = false,
+                        _ => {}
+                    }
+                    continue;
+                }
                 Event::KeyDown {
                     keycode: Some(keycode),
                     repeat,
                     keymod,
                     ..
                 } => {
-                    if repeat {
+                    // Holding backspace should remove more than one character.
+                    if self.text_input_enabled {
+                        self.text_input_events.push(TextEvent::Key(keycode, keymod));
                         continue;
                     }
-                    if self.text_input_enabled {
-                        self.text_input_events.push((keycode, keymod));
+                    if repeat {
                         continue;
                     }
                     (Device::Key(keycode), KeyState::Down(frame))
                 }
+                Event::TextInput { text, .. } => {
+                    if self.text_input_enabled {
+                        self.text_composition.clear();
+                        self.text_input_events.push(TextEvent::Text(text));
+                    }
+                    continue;
+                }
+                Event::TextEditing { text, .. } => {
+                    if self.text_input_enabled {
+                        self.text_composition = text;
+                    }
+                    continue;
+                }
                 Event::KeyUp {
                     keycode: Some(keycode),
                     ..
@@ -270,7 +616,10 @@ where
                 Event::ControllerAxisMotion {
                     which, axis, value, ..
                 } => {
-                    let value = remap(value);
+                    let value = match axis {
+                        Axis::TriggerLeft | Axis::TriggerRight => remap_trigger(value),
+                        _ => remap(value),
+                    };
                     (Device::Axis(which, axis), KeyState::Analog(value))
                 }
                 Event::ControllerButtonDown { which, button, .. } => {
@@ -280,9 +629,13 @@ where
                     (Device::Button(which, button), KeyState::Up(frame))
                 }
                 Event::MouseButtonDown { mouse_btn, .. } => {
+                    self.mouse_buttons.insert(mouse_btn, KeyState::Down(frame));
                     (Device::Mouse(mouse_btn), KeyState::Down(frame))
                 }
                 Event::MouseButtonUp { mouse_btn, .. } => {
+                    if let Some(KeyState::Down(_)) = self.mouse_buttons.get(&mouse_btn) {
+                        self.mouse_buttons.insert(mouse_btn, KeyState::Up(frame));
+                    }
                     (Device::Mouse(mouse_btn), KeyState::Up(frame))
                 }
                 Event::MouseMotion {
@@ -298,8 +651,51 @@ where
                 }
             };
 
+            let active = match down {
+                KeyState::Down(_) => true,
+                KeyState::Up(_) => false,
+                KeyState::Analog(v) => v.abs() > self.device_threshold(&input),
+            };
+            // Held since before a reset, see InputManager::reset.
+            let stale = matches!(down, KeyState::Up(_)) && !self.held.contains(&input);
+            if input != Device::Quit {
+                if active {
+                    self.last_input_device = Some(input.clone());
+                    self.held.insert(input.clone());
+                } else {
+                    self.held.remove(&input);
+                }
+            }
+
             if let Some(slot) = self.physical_inputs.get(&input) {
-                self.virtual_inputs.insert(slot.clone(), down);
+                if !stale {
+                    self.virtual_inputs.insert(slot.clone(), down);
+                }
+            }
+        }
+
+        self.update_combos();
+    }
+
+    /// Combos only change state when all their devices go down, or one of
+    /// them goes up, so they can share a name with other bindings.
+    fn update_combos(&mut self) {
+        let frame = self.frame;
+        for (device, name) in self.physical_inputs.iter() {
+            let devices = match device {
+                Device::Combo(devices) => devices,
+                _ => continue,
+            };
+            let down = devices.iter().all(|device| self.held.contains(device));
+            if down == self.combos_down.contains(device) {
+                continue;
+            }
+            if down {
+                self.combos_down.insert(device.clone());
+                self.virtual_inputs.insert(name.clone(), KeyState::Down(frame));
+            } else {
+                self.combos_down.remove(device);
+                self.virtual_inputs.insert(name.clone(), KeyState::Up(frame));
             }
         }
     }
@@ -1,16 +1,23 @@
-use luminance_sdl2::sdl2::{self, IntegerOrSdlError, surface::Surface, video::WindowPos};
+use luminance_sdl2::sdl2::{self, IntegerOrSdlError, surface::Surface, video::{SwapInterval, WindowPos}};
+use luminance::texture::{MagFilter, MinFilter, Sampler, Wrap};
 use luminance_sdl2::GL33Surface;
 use sdl2::audio::AudioDevice;
 use sdl2::Sdl;
-use std::{ffi::NulError, hash::Hash, path::Path};
+use std::{ffi::NulError, fmt, hash::Hash, marker::PhantomData, path::Path};
 use std::time::Instant;
 
 pub mod audio;
 pub mod asset;
+pub mod collision;
 pub mod input;
 pub mod random;
 pub mod renderer;
 pub mod performance;
+pub mod scene;
+pub mod tween;
+
+/// The most time, in seconds, that's saved up for fixed steps.
+const MAX_ACCUMULATED_TIME: f32 = 0.25;
 
 /// Everything you need to create a game.
 pub struct Game<T> {
@@ -20,72 +27,473 @@ pub struct Game<T> {
     pub input: input::InputManager<T>,
 
     surface: GL33Surface,
+    frame: u64,
     start_t: Instant,
     delta: f32,
     prev_t: f32,
+    real_delta: f32,
+    real_t: f32,
+    time_scale: f32,
+
+    /// The length of a fixed step, in seconds.
+    fixed_delta: f32,
+    /// Time that hasn't been simulated in fixed steps yet.
+    accumulator: f32,
+
+    debug_overlay_toggle: Option<T>,
+    show_debug_overlay: bool,
+}
+
+/// Which part of the [Game] couldn't be created, with the error from SDL.
+#[derive(Debug)]
+pub enum GameError {
+    /// The window or the OpenGL context.
+    Surface(String),
+    /// Usually because there's no audio device.
+    Audio(String),
+    Input(String),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameError::Surface(err) => write!(f, "failed to create the window: {}", err),
+            GameError::Audio(err) => write!(f, "failed to open the audio device: {}", err),
+            GameError::Input(err) => write!(f, "failed to start input: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+/// Settings for creating a [Game], see [Game::builder].
+pub struct GameBuilder<T> {
+    title: String,
+    size: (u32, u32),
+    hidden: bool,
+    fullscreen: bool,
+    resizable: bool,
+    allow_highdpi: bool,
+    vsync: bool,
+    smooth_filtering: bool,
+    linear_color: bool,
+    audio_channels: u8,
+    audio_buffer_size: u16,
+    marker: PhantomData<T>,
+}
+
+impl<T: Eq + Hash + Clone> GameBuilder<T> {
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = title.to_string();
+        self
+    }
+
+    /// The size of the window. Defaults to 800 by 600.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = (width, height);
+        self
+    }
+
+    /// Never shows the window, see [Game::new_headless].
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Covers the whole screen, at the resolution of the desktop.
+    pub fn fullscreen(mut self, fullscreen: bool) -> Self {
+        self.fullscreen = fullscreen;
+        self
+    }
+
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+
+    /// Renders at the full resolution of high-DPI screens, like Retina displays.
+    /// The window and mouse are still measured in points, see [Game::drawable_size].
+    pub fn allow_highdpi(mut self, allow_highdpi: bool) -> Self {
+        self.allow_highdpi = allow_highdpi;
+        self
+    }
+
+    /// Waits for the screen to refresh before showing a new frame. On by default.
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
+    /// See [Game::set_smooth_filtering]. Off by default, which suits pixel art.
+    pub fn smooth_filtering(mut self, smooth: bool) -> Self {
+        self.smooth_filtering = smooth;
+        self
+    }
+
+    /// Asks for a window that can blend in linear space, and turns it on.
+    /// See [renderer::Renderer::set_linear_color]. Off by default, since not
+    /// every driver has such windows.
+    pub fn linear_color(mut self, linear_color: bool) -> Self {
+        self.linear_color = linear_color;
+        self
+    }
+
+    /// See [audio::Audio::init_with]. Defaults to stereo.
+    pub fn audio_channels(mut self, channels: u8) -> Self {
+        self.audio_channels = channels;
+        self
+    }
+
+    /// See [audio::Audio::init_with].
+    pub fn audio_buffer_size(mut self, buffer_size: u16) -> Self {
+        self.audio_buffer_size = buffer_size;
+        self
+    }
+
+    pub fn build(self) -> Game<T> {
+        self.try_build().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like [GameBuilder::build], but returns what went wrong instead of panicking.
+    pub fn try_build(self) -> Result<Game<T>, GameError> {
+        let surface = GL33Surface::build_with(|video| {
+            if self.linear_color {
+                video.gl_attr().set_framebuffer_srgb_compatible(true);
+            }
+            let mut window = video.window(&self.title, self.size.0, self.size.1);
+            if self.hidden {
+                window.hidden();
+            }
+            if self.fullscreen {
+                window.fullscreen_desktop();
+            }
+            if self.resizable {
+                window.resizable();
+            }
+            if self.allow_highdpi {
+                window.allow_highdpi();
+            }
+            window
+        })
+        .map_err(|err| GameError::Surface(format!("{:?}", err)))?;
+        Game::with_surface(surface, &self)
+    }
 }
 
 impl<T: Eq + Hash + Clone> Game<T> {
     pub fn new(title: &str, window_width: u32, window_height: u32) -> Self {
-        let mut surface = GL33Surface::build_with(|video| video.window(title,
-                                                                       window_width,
-                                                                       window_height))
-            .expect("Failed to create surface");
+        Self::builder()
+            .title(title)
+            .size(window_width, window_height)
+            .build()
+    }
+
+    /// Like [Game::new], but returns what went wrong instead of panicking.
+    pub fn try_new(title: &str, window_width: u32, window_height: u32) -> Result<Self, GameError> {
+        Self::builder()
+            .title(title)
+            .size(window_width, window_height)
+            .try_build()
+    }
+
+    /// Like [Game::new], but the window is never shown. Useful for tests,
+    /// together with [Game::render_to_image].
+    ///
+    /// Audio still needs a device, set `SDL_AUDIODRIVER=dummy` if there isn't one.
+    pub fn new_headless(window_width: u32, window_height: u32) -> Self {
+        Self::builder()
+            .size(window_width, window_height)
+            .hidden(true)
+            .build()
+    }
+
+    /// For when [Game::new] isn't enough.
+    ///
+    /// ```ignore
+    /// let game: Game<Name> = Game::builder()
+    ///     .title("Hello")
+    ///     .size(1280, 720)
+    ///     .vsync(false)
+    ///     .build();
+    /// ```
+    pub fn builder() -> GameBuilder<T> {
+        GameBuilder {
+            title: "lingon".to_string(),
+            size: (800, 600),
+            hidden: false,
+            fullscreen: false,
+            resizable: false,
+            allow_highdpi: false,
+            vsync: true,
+            smooth_filtering: false,
+            linear_color: false,
+            audio_channels: 2,
+            audio_buffer_size: audio::DEFAULT_BUFFER_SIZE,
+            marker: PhantomData,
+        }
+    }
+
+    fn with_surface(mut surface: GL33Surface, builder: &GameBuilder<T>) -> Result<Self, GameError> {
+        let swap_interval = if builder.vsync { SwapInterval::VSync } else { SwapInterval::Immediate };
+        // Not every driver lets us choose, and that's fine.
+        if let Ok(video) = surface.sdl().video() {
+            let _ = video.gl_set_swap_interval(swap_interval);
+        }
 
-        let mut sampler = luminance::texture::Sampler::default();
-        sampler.mag_filter = luminance::texture::MagFilter::Nearest;
-        let renderer = renderer::Renderer::new(&mut surface, sampler);
+        let sampler = if builder.smooth_filtering {
+            Sampler {
+                mag_filter: MagFilter::Linear,
+                min_filter: MinFilter::Linear,
+                ..Sampler::default()
+            }
+        } else {
+            Sampler {
+                mag_filter: MagFilter::Nearest,
+                ..Sampler::default()
+            }
+        };
+        let mut renderer = renderer::Renderer::new(&mut surface, sampler);
+        renderer.set_linear_color(builder.linear_color);
 
-        let audio = audio::Audio::init(surface.sdl());
+        let audio = audio::Audio::try_init_with(
+            surface.sdl(),
+            builder.audio_channels,
+            builder.audio_buffer_size,
+        )
+        .map_err(GameError::Audio)?;
         audio.resume();
         let assets = asset::AssetSystem::new();
 
-        let input = input::InputManager::new(surface.sdl());
+        let input = input::InputManager::try_new(surface.sdl()).map_err(GameError::Input)?;
 
-        Self {
+        Ok(Self {
             audio,
             assets,
             renderer,
             input,
 
             surface,
+            frame: 0,
             start_t: Instant::now(),
             delta: 0.0,
             prev_t: 0.0,
-        }
+            real_delta: 0.0,
+            real_t: 0.0,
+            time_scale: 1.0,
+
+            fixed_delta: 1.0 / 60.0,
+            accumulator: 0.0,
+
+            debug_overlay_toggle: None,
+            show_debug_overlay: false,
+        })
     }
 
+    /// Starts a new frame. Call it once per frame, before anything else.
+    ///
+    /// The engine keeps track of time itself, there's no delta to pass in.
+    /// This measures the time since the last call, which is then read with
+    /// [Game::delta], and polls input and reloads assets.
     pub fn update(&mut self) {
+        self.frame += 1;
         let t = self.start_t.elapsed().as_millis() as f32 * 1e-3;
-        self.delta = t - self.prev_t;
-        self.prev_t = t;
+        self.real_delta = t - self.real_t;
+        self.real_t = t;
+        self.delta = self.real_delta * self.time_scale;
+        self.prev_t += self.delta;
+        // Don't try to catch up forever if we fall behind.
+        self.accumulator = (self.accumulator + self.delta).min(MAX_ACCUMULATED_TIME);
 
         performance::frame();
         self.assets.reload();
-        self.renderer.reload();
+        // Shader errors are shown on the debug overlay.
+        let _ = self.renderer.reload(&mut self.surface);
         self.input.poll(self.surface.sdl());
+
+        if let Some(toggle) = &self.debug_overlay_toggle {
+            if self.input.pressed(toggle.clone()) {
+                self.show_debug_overlay = !self.show_debug_overlay;
+            }
+        }
+    }
+
+    /// Renders everything pushed this frame to an image the size of the window, in pixels.
+    /// See [renderer::Renderer::render_to_image].
+    pub fn render_to_image(&mut self) -> Result<asset::Image, ()> {
+        let (w, h) = self.drawable_size();
+        self.renderer.render_to_image(&mut self.surface, (w as usize, h as usize))
     }
 
     pub fn draw(&mut self) -> Result<(), ()> {
+        if self.show_debug_overlay {
+            self.renderer.draw_debug_overlay(&performance::PERF_COUNTER.lock().unwrap());
+        }
         self.renderer.render(&mut self.surface)
     }
 
+    /// Runs the scenes until there are none left, or rendering fails.
+    /// Updates, draws and renders once per frame. See [scene].
+    pub fn run(&mut self, mut scenes: scene::SceneStack<T>) {
+        while !scenes.is_empty() {
+            self.update();
+            scenes.update(self);
+            scenes.draw(self);
+            if self.draw().is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Toggles the performance overlay when the input is pressed.
+    /// See [renderer::Renderer::draw_debug_overlay].
+    pub fn set_debug_overlay_toggle(&mut self, name: T) {
+        self.debug_overlay_toggle = Some(name);
+    }
+
+    pub fn show_debug_overlay(&self) -> bool {
+        self.show_debug_overlay
+    }
+
+    pub fn set_show_debug_overlay(&mut self, show: bool) {
+        self.show_debug_overlay = show;
+    }
+
+    /// See [renderer::Renderer::set_sprite_sheet_size].
+    pub fn set_sprite_sheet_size(&mut self, size: [u32; 3]) {
+        self.renderer.set_sprite_sheet_size(&mut self.surface, size);
+    }
+
+    /// Switches between nearest filtering, the default that suits pixel art,
+    /// and linear filtering for smooth sprites.
+    pub fn set_smooth_filtering(&mut self, smooth: bool) {
+        let sampler = Sampler {
+            mag_filter: if smooth { MagFilter::Linear } else { MagFilter::Nearest },
+            min_filter: if smooth { MinFilter::Linear } else { MinFilter::Nearest },
+            ..*self.renderer.sampler()
+        };
+        self.renderer.set_sampler(&mut self.surface, sampler);
+    }
+
+    /// What happens to texture coordinates outside the sprite sheet. The
+    /// default, [Wrap::ClampToEdge], stretches the edge. [Wrap::Repeat] tiles
+    /// the sheet, see [renderer::Sprite::scroll_uv].
+    pub fn set_texture_wrap(&mut self, wrap: Wrap) {
+        let sampler = Sampler {
+            wrap_s: wrap,
+            wrap_t: wrap,
+            ..*self.renderer.sampler()
+        };
+        self.renderer.set_sampler(&mut self.surface, sampler);
+    }
+
     pub fn sdl(&self) -> &Sdl {
         self.surface.sdl()
     }
 
+    /// The number of times [Game::update] has been called. Counts every
+    /// frame, even when the time scale is 0.
+    pub fn frame_number(&self) -> u64 {
+        self.frame
+    }
+
+    /// Seconds since the last update, scaled by the time scale.
     pub fn delta(&self) -> f32 {
         self.delta
     }
 
+    /// Seconds of scaled time since the game started.
     pub fn total_time(&self) -> f32 {
         self.prev_t
     }
 
+    /// Seconds since the last update, ignoring the time scale.
+    pub fn real_delta(&self) -> f32 {
+        self.real_delta
+    }
+
+    /// Seconds of wall clock time since the game started.
+    pub fn real_time(&self) -> f32 {
+        self.real_t
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Scales [Self::delta] and [Self::total_time]. `0.5` is slow motion and
+    /// `0.0` pauses the game while still letting it render.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        assert!(time_scale >= 0.0);
+        self.time_scale = time_scale;
+    }
+
+    /// The length of a fixed step, in seconds. Defaults to 1/60.
+    pub fn fixed_delta(&self) -> f32 {
+        self.fixed_delta
+    }
+
+    pub fn set_fixed_delta(&mut self, fixed_delta: f32) {
+        assert!(fixed_delta > 0.0);
+        self.fixed_delta = fixed_delta;
+    }
+
+    /// Returns true if there's time for another fixed step, and consumes it.
+    /// Simulates at the same rate no matter the frame rate:
+    ///
+    /// ```ignore
+    /// game.update();
+    /// while game.fixed_step() {
+    ///     simulate(game.fixed_delta());
+    /// }
+    /// draw(game.interpolation_alpha());
+    /// ```
+    pub fn fixed_step(&mut self) -> bool {
+        if self.accumulator >= self.fixed_delta {
+            self.accumulator -= self.fixed_delta;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How far, in `[0, 1)`, we are between the last fixed step and the next.
+    /// Use it to interpolate between the previous and current simulation state.
+    pub fn interpolation_alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_delta).min(1.0 - f32::EPSILON)
+    }
+
+    /// Returns the new size of the window if it was resized this frame.
+    /// The renderer picks up the new size by itself.
+    pub fn window_resized(&self) -> Option<(u32, u32)> {
+        self.input.window_resized()
+    }
+
+    /// The mouse position in world coordinates, as seen by the renderer's camera.
+    pub fn mouse_world(&self) -> (f32, f32) {
+        let (w, h) = self.window_size();
+        let (x, y) = self.input.mouse();
+        // Pixels to fractions of the window, with y pointing up.
+        let x = x as f32 / w as f32;
+        let y = 1.0 - y as f32 / h as f32;
+        // Fractions of the letterboxed area to [-1, 1].
+        let [lx, ly, lw, lh] = self.renderer.letterbox((w, h));
+        let x = (x - lx) / lw * 2.0 - 1.0;
+        let y = (y - ly) / lh * 2.0 - 1.0;
+        self.renderer.camera.screen_to_world(x, y)
+    }
+
+    /// The size of the window in points, the unit of the mouse position.
     pub fn window_size(&self) -> (u32, u32) {
         self.surface.window().size()
     }
 
+    /// The size of the window in pixels, which is what's rendered to.
+    /// Larger than [Game::window_size] on high-DPI screens, see
+    /// [GameBuilder::allow_highdpi].
+    pub fn drawable_size(&self) -> (u32, u32) {
+        self.surface.window().drawable_size()
+    }
+
     pub fn set_window_size(&mut self, width: u32, height: u32) -> Result<(), IntegerOrSdlError> {
         self.surface.window_mut().set_size(width, height)
     }
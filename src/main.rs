@@ -15,8 +15,10 @@ pub enum Name {
     Right,
     Up,
     Down,
+    Move,
     PlaySound,
     Quit,
+    DebugOverlay,
 }
 
 fn bind_inputs(game: &mut lingon::Game<Name>) {
@@ -27,8 +29,11 @@ fn bind_inputs(game: &mut lingon::Game<Name>) {
     game.input.bind(input::Device::Key(input::Keycode::Escape), Name::Quit);
     game.input.bind(input::Device::Key(input::Keycode::F), Name::PlaySound);
     game.input.bind(input::Device::Quit, Name::Quit);
+    game.input.bind(input::Device::Key(input::Keycode::F3), Name::DebugOverlay);
+    game.set_debug_overlay_toggle(Name::DebugOverlay);
     game.input.bind(input::Device::Axis(0, input::Axis::LeftX), Name::Right);
     game.input.bind(input::Device::Axis(0, input::Axis::RightY), Name::Up);
+    game.input.bind_vector(Name::Up, Name::Down, Name::Left, Name::Right, Name::Move);
 }
 
 fn main() {
@@ -108,8 +113,10 @@ fn main() {
         }
 
         // Move the particle system in a circle. One revolution takes 2*PI seconds.
-        particle_system.position[0] = game.total_time().cos() * 0.5;
-        particle_system.position[1] = game.total_time().sin() * 0.5;
+        particle_system.at(
+            game.total_time().cos() * 0.5,
+            game.total_time().sin() * 0.5,
+        );
 
         // Spawn five particles each frame.
         for _ in 0..5 {
@@ -170,10 +177,8 @@ fn main() {
         game.renderer.push_particle_system(&particle_system);
 
         // Tell the renderer to move the camera.
-        game.renderer.camera.move_by(
-            (game.input.value(Name::Right) - game.input.value(Name::Left)) * game.delta(),
-            (game.input.value(Name::Up) - game.input.value(Name::Down)) * game.delta(),
-        );
+        let (x, y) = game.input.vector(Name::Move);
+        game.renderer.camera.move_by(x * game.delta(), y * game.delta());
 
         // Draw this frame.
         if game.draw().is_err() {
@@ -1,3 +1,4 @@
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use lazy_static::lazy_static;
 use std::time::Instant;
@@ -22,11 +23,16 @@ pub struct Counter {
     name: &'static str,
     file: &'static str,
     line: u32,
+    /// The counter that was running when this one was first started.
+    parent: Option<usize>,
 
     total_calls: usize,
     calls_this_frame: usize,
     total_time: f64,
     time_this_frame: f64,
+    /// Time spent in child counters.
+    total_child_time: f64,
+    child_time_this_frame: f64,
 }
 
 impl Counter {
@@ -37,20 +43,62 @@ impl Counter {
             name,
             file,
             line,
+            parent: None,
 
             total_calls: 0,
             calls_this_frame: 0,
             total_time: 0.0,
             time_this_frame: 0.0,
+            total_child_time: 0.0,
+            child_time_this_frame: 0.0,
         }
     }
 
-    fn add(&mut self, start: Instant) {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn file(&self) -> &'static str {
+        self.file
+    }
+
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The average time of a call this frame, in seconds.
+    pub fn average_time_this_frame(&self) -> f64 {
+        self.time_this_frame / (self.calls_this_frame as f64)
+    }
+
+    /// The average time of a call since the start, in seconds.
+    pub fn average_time(&self) -> f64 {
+        self.total_time / (self.total_calls as f64)
+    }
+
+    /// Like [Counter::average_time_this_frame], but without the time spent in children.
+    pub fn average_exclusive_time_this_frame(&self) -> f64 {
+        (self.time_this_frame - self.child_time_this_frame) / (self.calls_this_frame as f64)
+    }
+
+    /// Like [Counter::average_time], but without the time spent in children.
+    pub fn average_exclusive_time(&self) -> f64 {
+        (self.total_time - self.total_child_time) / (self.total_calls as f64)
+    }
+
+    /// Returns the time of the call.
+    fn add(&mut self, start: Instant) -> f64 {
         self.total_calls += 1;
         self.calls_this_frame += 1;
         let time = Instant::now().duration_since(start).as_secs_f64();
         self.total_time += time;
         self.time_this_frame += time;
+        time
+    }
+
+    fn add_child_time(&mut self, time: f64) {
+        self.total_child_time += time;
+        self.child_time_this_frame += time;
     }
 }
 
@@ -89,6 +137,21 @@ pub fn capture_for(window: CaptureWindow) {
     PERF_COUNTER.lock().unwrap().borrow_mut().window = window;
 }
 
+/// Frames that take longer than this are counted as spikes.
+pub fn set_budget_ms(budget_ms: f64) {
+    PERF_COUNTER.lock().unwrap().borrow_mut().budget_ms = budget_ms;
+}
+
+/// The last [FRAME_HISTORY] frame times in seconds, oldest first.
+pub fn recent_frame_times() -> Vec<f32> {
+    PERF_COUNTER.lock().unwrap().frame_times().map(|t| t as f32).collect()
+}
+
+/// Does nothing unless the `profiling` feature is enabled or it's a debug build.
+#[cfg(not(any(feature = "profiling", debug_assertions)))]
+pub fn frame() {}
+
+#[cfg(any(feature = "profiling", debug_assertions))]
 pub fn frame() {
     let mut counter = PERF_COUNTER.lock().unwrap();
     let counter = counter.borrow_mut();
@@ -100,6 +163,17 @@ pub fn frame() {
     }
 }
 
+/// Times the scope it's in. Expands to nothing unless the `profiling`
+/// feature is enabled or it's a debug build.
+#[cfg(not(any(feature = "profiling", debug_assertions)))]
+#[macro_export]
+macro_rules! counter {
+    ( $name:expr ) => {
+        ()
+    };
+}
+
+#[cfg(any(feature = "profiling", debug_assertions))]
 #[macro_export]
 macro_rules! counter {
     ( $name:expr ) => {
@@ -109,15 +183,22 @@ macro_rules! counter {
                 std::file!(),
                 std::line!(),
             );
-            let counter = lingon_macro::perf_counter!();
-            lingon::performance::PERF_COUNTER.lock().unwrap().start(counter, info)
+            lingon::performance::PERF_COUNTER.lock().unwrap().start(info)
         }
     };
 }
 
+/// How many frame times are kept around for graphing.
+pub const FRAME_HISTORY: usize = 100;
+
 pub struct Collector {
-    counters: Vec<Option<Counter>>,
+    counters: Vec<Counter>,
+    /// Counters are identified by where they're created.
+    counter_ids: HashMap<(&'static str, u32), usize>,
+    /// The counters that are currently running, innermost last.
+    active: Vec<usize>,
     window: CaptureWindow,
+    frame_times: VecDeque<f64>,
 
     start: Instant,
     num_frames: usize,
@@ -126,12 +207,19 @@ pub struct Collector {
     total_time: f64,
     min_frame_time: f64,
     max_frame_time: f64,
+
+    budget_ms: f64,
+    frames_over_budget: usize,
+    last_frame_over_budget: Option<usize>,
 }
 
 impl Collector {
     fn new() -> Self {
         Self {
             counters: Vec::new(),
+            counter_ids: HashMap::new(),
+            active: Vec::new(),
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
 
             window: CaptureWindow::LogEvery(100),
             start: Instant::now(),
@@ -141,17 +229,23 @@ impl Collector {
             total_time: 0.0,
             min_frame_time: f64::MAX,
             max_frame_time: f64::MIN,
-        }
-    }
 
-    pub fn start(&mut self, id: usize, counter: Counter) -> Marker {
-        if self.counters.len() <= id {
-            self.counters.resize_with(id + 1, || None);
+            budget_ms: 1000.0 / 60.0,
+            frames_over_budget: 0,
+            last_frame_over_budget: None,
         }
+    }
 
-        if matches!(self.counters[id], None) {
-            self.counters[id] = Some(counter);
-        }
+    /// Counters started while another counter is running become its children.
+    pub fn start(&mut self, mut counter: Counter) -> Marker {
+        let counters = &mut self.counters;
+        let parent = self.active.last().copied();
+        let id = *self.counter_ids.entry((counter.file, counter.line)).or_insert_with(|| {
+            counter.parent = parent;
+            counters.push(counter);
+            counters.len() - 1
+        });
+        self.active.push(id);
         Marker {
             id,
             start: Instant::now(),
@@ -159,7 +253,14 @@ impl Collector {
     }
 
     pub fn end(&mut self, marker: &mut Marker) {
-        self.counters.get_mut(marker.id).unwrap().as_mut().unwrap().add(marker.start);
+        // Markers can be dropped out of order.
+        if let Some(i) = self.active.iter().rposition(|&id| id == marker.id) {
+            self.active.remove(i);
+        }
+        let time = self.counters[marker.id].add(marker.start);
+        if let Some(&parent) = self.active.last() {
+            self.counters[parent].add_child_time(time);
+        }
     }
 
     pub fn frame(&mut self) {
@@ -174,26 +275,109 @@ impl Collector {
         self.max_frame_time = frame_time.max(self.max_frame_time);
         self.last_time = frame_time;
 
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+
+        if frame_time * 1000.0 > self.budget_ms {
+            self.frames_over_budget += 1;
+            self.last_frame_over_budget = Some(self.num_frames);
+        }
+
         let weighting = 0.8;
         self.weighted_time = self.weighted_time * (1.0 - weighting) + frame_time * weighting;
     }
 
+    pub fn num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    /// The time of the last frame, in seconds.
+    pub fn last_frame_time(&self) -> f64 {
+        self.last_time
+    }
+
+    /// A smoothed frame time, in seconds.
+    pub fn weighted_frame_time(&self) -> f64 {
+        self.weighted_time
+    }
+
+    /// The average frame time since the start, in seconds.
+    pub fn average_frame_time(&self) -> f64 {
+        self.total_time / (self.num_frames as f64)
+    }
+
+    pub fn min_frame_time(&self) -> f64 {
+        self.min_frame_time
+    }
+
+    pub fn max_frame_time(&self) -> f64 {
+        self.max_frame_time
+    }
+
+    /// The frame budget in milliseconds, 60 FPS by default. See [set_budget_ms].
+    pub fn budget_ms(&self) -> f64 {
+        self.budget_ms
+    }
+
+    /// How many frames have taken longer than the budget.
+    pub fn frames_over_budget(&self) -> usize {
+        self.frames_over_budget
+    }
+
+    /// The number of the last frame that took longer than the budget.
+    /// Compare with [Collector::num_frames].
+    pub fn last_frame_over_budget(&self) -> Option<usize> {
+        self.last_frame_over_budget
+    }
+
+    /// The last [FRAME_HISTORY] frame times, oldest first.
+    pub fn frame_times(&self) -> impl Iterator<Item = f64> + '_ {
+        self.frame_times.iter().copied()
+    }
+
+    /// All counters that have been started at least once.
+    pub fn counters(&self) -> impl Iterator<Item = &Counter> {
+        self.counters.iter()
+    }
+
+    /// All counters depth first, with how deep they are nested.
+    pub fn counter_tree(&self) -> Vec<(usize, &Counter)> {
+        fn visit<'a>(counters: &'a [Counter], parent: Option<usize>, depth: usize, out: &mut Vec<(usize, &'a Counter)>) {
+            for (id, counter) in counters.iter().enumerate() {
+                if counter.parent == parent {
+                    out.push((depth, counter));
+                    visit(counters, Some(id), depth + 1, out);
+                }
+            }
+        }
+        let mut tree = Vec::new();
+        visit(&self.counters, None, 0, &mut tree);
+        tree
+    }
+
     pub fn log(&mut self) {
-        println!("PERFORMANCE: #{}\nthis: {:<5.5} wgh: {:<5.5} avg: {:<5.5} min: {:<5.5} max: {:<5.5}",
+        println!("PERFORMANCE: #{}\nthis: {:<5.5} wgh: {:<5.5} avg: {:<5.5} min: {:<5.5} max: {:<5.5} over budget: {}",
             self.num_frames,
             self.last_time,
             self.weighted_time,
             self.total_time / (self.num_frames as f64),
             self.min_frame_time,
             self.max_frame_time,
+            self.frames_over_budget,
         );
-        for counter in self.counters.iter().filter_map(|x| x.as_ref()) {
-            println!(" {} ({}:{}) - {:<5.5} {:<5.5}",
+        for (depth, counter) in self.counter_tree() {
+            println!(" {:indent$}{} ({}:{}) - {:<5.5} {:<5.5} excl: {:<5.5} {:<5.5}",
+                "",
                 counter.name,
                 counter.file,
                 counter.line,
-                counter.time_this_frame / (counter.calls_this_frame as f64),
-                counter.total_time / (counter.total_calls as f64),
+                counter.average_time_this_frame(),
+                counter.average_time(),
+                counter.average_exclusive_time_this_frame(),
+                counter.average_exclusive_time(),
+                indent = depth * 2,
             )
         }
     }
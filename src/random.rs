@@ -23,12 +23,58 @@ impl RandomProperty {
         }
     }
 
+    /// Always gives the same value.
+    pub fn constant(value: f32) -> Self {
+        Self::new(value, value, Box::new(NoDice))
+    }
+
     /// Samples a random value in the given range.
     pub fn sample(&self) -> f32 {
         self.distribution.between(self.range[0], self.range[1])
     }
 }
 
+/// How to move between two values over time.
+///
+/// The discriminants are used to select the easing in shaders.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Easing {
+    /// Moves at a constant speed.
+    Linear = 0,
+    /// Starts slow and speeds up.
+    EaseIn = 1,
+    /// Starts fast and slows down.
+    EaseOut = 2,
+    /// Starts slow, speeds up and slows down again.
+    EaseInOut = 3,
+    /// Like [Easing::EaseInOut], but a bit softer.
+    SmoothStep = 4,
+}
+
+impl Default for Easing {
+    fn default() -> Self {
+        Easing::Linear
+    }
+}
+
+impl Easing {
+    /// Takes a value between 0.0 and 1.0 and eases it.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => if t < 0.5 {
+                2.0 * t * t
+            } else {
+                1.0 - 2.0 * (1.0 - t) * (1.0 - t)
+            },
+            Easing::SmoothStep => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
 pub trait Distribute {
     /// Get a random value between 0.0 and 1.0.
     fn sample(&self) -> f32;
@@ -83,3 +129,65 @@ impl Distribute for Square {
         Ra::ggen::<f32>() * Ra::ggen::<f32>()
     }
 }
+
+/// A continuous bias. Powers above 1.0 are biased towards the lowest value,
+/// powers below 1.0 towards the highest. A power of 1.0 is [Uniform].
+pub struct Biased {
+    pub power: f32,
+}
+
+impl Distribute for Biased {
+    fn sample(&self) -> f32 {
+        Ra::ggen::<f32>().powf(self.power)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EASINGS: [Easing; 5] = [
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+        Easing::SmoothStep,
+    ];
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in EASINGS.iter() {
+            assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_are_clamped() {
+        for easing in EASINGS.iter() {
+            assert_eq!(easing.apply(-1.0), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(2.0), 1.0, "{:?}", easing);
+        }
+    }
+
+    #[test]
+    fn easings_never_go_backwards() {
+        for easing in EASINGS.iter() {
+            let mut last = 0.0;
+            for i in 0..=100 {
+                let value = easing.apply(i as f32 / 100.0);
+                assert!(value >= last, "{:?} at {}", easing, i);
+                last = value;
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_easings_are_halfway_in_the_middle() {
+        assert_eq!(Easing::Linear.apply(0.5), 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+        assert_eq!(Easing::SmoothStep.apply(0.5), 0.5);
+        assert_eq!(Easing::EaseIn.apply(0.5), 0.25);
+        assert_eq!(Easing::EaseOut.apply(0.5), 0.75);
+    }
+}
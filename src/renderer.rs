@@ -19,33 +19,52 @@
 //!
 //! Since the renderer is based around
 //! [instancing](https://www.khronos.org/opengl/wiki/Vertex_Rendering#Instancing),
-//! some things (like skewing) are harder to do.
+//! some things are harder to do. Skewing is limited to an axis-aligned
+//! shear, see [Shear].
+//!
+//! Everything is drawn in the order it is pushed. Stamps that are pushed
+//! one after another with the same render state (see [BatchKey]) are merged
+//! into a single draw call. Changing the state, or pushing a particle system
+//! or a trail, ends the current batch. Layers override the push order, see
+//! [Renderer::set_layer].
 
-pub use crate::renderer::particles::ParticleSystem;
+pub use crate::renderer::bitmap_font::BitmapFont;
+pub use crate::renderer::particles::{ParticleSystem, Simulation, SpawnShape};
+pub use crate::renderer::trail::Trail;
 
 use crate::asset::{Image, Font, Pixels};
+#[cfg(debug_assertions)]
+use crate::asset::LoadedFile;
+#[cfg(debug_assertions)]
+use std::path::Path;
+use crate::performance::Collector;
 use crate::renderer::particles::FrozenParticles;
 use luminance_glyph::{
     Section,
+    Text,
     FontId,
     GlyphBrush,
     GlyphBrushBuilder,
     ab_glyph::FontArc,
 };
 
-use cgmath::Vector2;
+use cgmath::{Matrix4, SquareMatrix, Vector2};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 use luminance::{blending::{Blending, Equation, Factor}, context::GraphicsContext};
-use luminance::pipeline::PipelineState;
-use luminance::pixel::NormRGBA8UI;
+use luminance::framebuffer::Framebuffer;
+use luminance::pipeline::{Pipeline, PipelineError, PipelineState, Viewport as GLViewport};
+use luminance::pixel::{Depth32F, NormRGBA8UI};
+use luminance::depth_test::DepthComparison;
 use luminance::render_state::RenderState;
-use luminance::tess::Mode;
-use luminance::shader::Program;
-use luminance::texture::{Dim3, GenMipmaps, Sampler, Texture};
+use luminance::tess::{Interleaved, Mode, Tess, View};
+use luminance::shader::{Program, ProgramError};
+use luminance::shading_gate::ShadingGate;
+use luminance::texture::{Dim2, Dim3, GenMipmaps, Sampler, Texture};
 use luminance_sdl2::GL33Surface;
 
-pub mod particles;
-mod prelude;
-
 // Me no likey, but at least it's not documented.
 use crate::renderer::prelude::*;
 
@@ -62,9 +81,9 @@ const VS_STR: &str = include_str!("vs.glsl");
 const FS_STR: &str = include_str!("fs.glsl");
 /// Particle vertex shader source code.
 const VS_PARTICLE_STR: &str = include_str!("vs_particle.glsl");
-/// The maximum size of a sprite sheet, and the maximum number of
-/// sprite sheets.
-const SPRITE_SHEET_SIZE: [u32; 3] = [512, 512, 512];
+/// The default maximum size of a sprite sheet, and the maximum number of
+/// sprite sheets. Can be changed with [Renderer::set_sprite_sheet_size].
+pub const SPRITE_SHEET_SIZE: [u32; 3] = [512, 512, 512];
 
 /// A simple rectangle for rendering sprites and the like.
 const RECT: [Vertex; 6] = [
@@ -82,22 +101,82 @@ pub struct SpriteSheet {
     id: usize,
     image: Image,
     tile_size: (Pixels, Pixels),
+    /// The size of the texture the sheet lives in.
+    sheet_size: [u32; 3],
+    /// Shrink regions by half a texel on each side, see [SpriteSheet::set_half_texel_inset].
+    half_texel_inset: bool,
 }
 
 impl SpriteSheet {
     /// Returns the SpriteRegion of a tile given the specified tile sizes,
     /// starting from the top left.
     pub fn grid(&self, tx: usize, ty: usize) -> SpriteRegion {
-        let xlo = ((self.tile_size.0 * tx) as f32) / (SPRITE_SHEET_SIZE[0] as f32);
-        let ylo = ((self.tile_size.1 * ty) as f32) / (SPRITE_SHEET_SIZE[1] as f32);
-        let w = (self.tile_size.0 as f32) / (SPRITE_SHEET_SIZE[0] as f32);
-        let h = (self.tile_size.1 as f32) / (SPRITE_SHEET_SIZE[1] as f32);
+        let texel_w = 1.0 / (self.sheet_size[0] as f32);
+        let texel_h = 1.0 / (self.sheet_size[1] as f32);
+        let xlo = (self.tile_size.0 * tx) as f32 * texel_w;
+        let ylo = (self.tile_size.1 * ty) as f32 * texel_h;
+        let w = self.tile_size.0 as f32 * texel_w;
+        let h = self.tile_size.1 as f32 * texel_h;
+        let (ix, iy) = if self.half_texel_inset {
+            (texel_w * 0.5, texel_h * 0.5)
+        } else {
+            (0.0, 0.0)
+        };
         (
-            self.id as f32 / (SPRITE_SHEET_SIZE[2] as f32),
-            [xlo, ylo, xlo + w, ylo + h],
+            self.id as f32 / (self.sheet_size[2] as f32),
+            [xlo + ix, ylo + iy, xlo + w - ix, ylo + h - iy],
         )
     }
 
+    /// The inverse of [SpriteSheet::grid]. Returns `(x, y, width, height)` in
+    /// pixels, where [SpriteSheet::grid] of tile `(tx, ty)` starts at
+    /// `(tx * tile width, ty * tile height)`.
+    ///
+    /// The region has to come from this sheet.
+    pub fn region_to_pixels(&self, region: SpriteRegion) -> (Pixels, Pixels, Pixels, Pixels) {
+        let (sheet, [u0, v0, u1, v1]) = region;
+        debug_assert_eq!(
+            (sheet * self.sheet_size[2] as f32).round() as usize,
+            self.id,
+            "the region is from another sprite sheet"
+        );
+        let (w, h) = (self.sheet_size[0] as f32, self.sheet_size[1] as f32);
+        // Undo the inset, if it was added.
+        let (ix, iy) = if self.half_texel_inset { (0.5, 0.5) } else { (0.0, 0.0) };
+        let x0 = (u0 * w - ix).round().max(0.0) as Pixels;
+        let y0 = (v0 * h - iy).round().max(0.0) as Pixels;
+        let x1 = (u1 * w + ix).round().max(0.0) as Pixels;
+        let y1 = (v1 * h + iy).round().max(0.0) as Pixels;
+        (x0, y0, x1.saturating_sub(x0), y1.saturating_sub(y0))
+    }
+
+    /// The size of a tile, in pixels.
+    pub fn tile_size(&self) -> (Pixels, Pixels) {
+        self.tile_size
+    }
+
+    /// The size of the image, in pixels.
+    pub fn dimensions(&self) -> (Pixels, Pixels) {
+        (self.image.width, self.image.height)
+    }
+
+    /// The number of tiles in a row.
+    pub fn tiles_x(&self) -> usize {
+        self.image.width / self.tile_size.0
+    }
+
+    /// The number of tiles in a column.
+    pub fn tiles_y(&self) -> usize {
+        self.image.height / self.tile_size.1
+    }
+
+    /// Moves the edges of the regions from [SpriteSheet::grid] half a texel
+    /// inwards, so neighbouring tiles don't bleed in when filtering or
+    /// when sprites land between pixels.
+    pub fn set_half_texel_inset(&mut self, enabled: bool) {
+        self.half_texel_inset = enabled;
+    }
+
     pub fn upload(&self, tex: &mut Tex) {
         tex.upload_part_raw(
             GenMipmaps::No,
@@ -108,8 +187,19 @@ impl SpriteSheet {
         .unwrap();
     }
 
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Changes to the image are uploaded on the next [Renderer::reload],
+    /// if the image is marked as [dirty](Image::dirty).
+    pub fn image_mut(&mut self) -> &mut Image {
+        &mut self.image
+    }
+
     pub fn reload(&mut self, tex: &mut Tex) {
-        if self.image.reload() {
+        if self.image.reload() || self.image.dirty {
+            self.image.dirty = false;
             self.upload(tex);
         }
     }
@@ -163,6 +253,12 @@ macro_rules! impl_transform_for {
     };
 }
 
+// Declared after the macros, so they can use them.
+pub mod bitmap_font;
+pub mod particles;
+mod prelude;
+pub mod trail;
+
 /// Manipulate and move things around.
 /// Designed to be chainable.
 pub trait Transform {
@@ -186,11 +282,23 @@ pub trait Transform {
     impl_transform!(deref, rotate,   +=, r:  f32 => r_mut);
     impl_transform!(deref, scale_by, *=, sx: f32 => sx_mut,        sy: f32 => sy_mut);
     impl_transform!(deref, scale,     =, sx: f32 => sx_mut,        sy: f32 => sy_mut);
+
+    /// Orbits a point in world space by `angle` radians, and turns by the
+    /// same amount, like the hands of a clock.
+    fn rotate_around(&mut self, pivot: (f32, f32), angle: f32) -> &mut Self {
+        let (dx, dy) = (*self.x_mut() - pivot.0, *self.y_mut() - pivot.1);
+        let (sin, cos) = angle.sin_cos();
+        *self.x_mut() = pivot.0 + dx * cos - dy * sin;
+        *self.y_mut() = pivot.1 + dx * sin + dy * cos;
+        self.rotate(angle)
+    }
 }
 
 /// Colorable things are Tint-able!
 pub trait Tint {
     fn color_mut(&mut self) -> &mut [f32; 4];
+    /// The current RGBA-color.
+    fn color(&self) -> [f32; 4];
 
     // TODO(ed): Comment on the functions the macro implement?
     impl_transform!(arr, rgb,  =,  r:  f32 => color_mut[0],  g:  f32 => color_mut[1], b: f32 => color_mut[2]);
@@ -203,9 +311,86 @@ pub trait Tint {
     fn tint(&mut self, r: f32, g: f32, b: f32, a: f32) -> &mut Self {
         self.rgba(r, g, b, a)
     }
+
+    /// Sets the color from a hue in degrees, and saturation and value
+    /// between 0 and 1. Keeps the alpha.
+    fn set_hsv(&mut self, h: f32, s: f32, v: f32) -> &mut Self {
+        let [r, g, b] = hsv_to_rgb([h, s, v]);
+        self.rgb(r, g, b)
+    }
+
+    /// The hue in degrees, and the saturation and value, of the color.
+    fn hsv(&self) -> [f32; 3] {
+        let [r, g, b, _] = self.color();
+        rgb_to_hsv([r, g, b])
+    }
+
+    /// Rotates the hue, keeping the saturation, value and alpha.
+    /// Shift it a bit every frame for a rainbow.
+    fn shift_hue(&mut self, degrees: f32) -> &mut Self {
+        let [h, s, v] = self.hsv();
+        self.set_hsv(h + degrees, s, v)
+    }
+}
+
+/// Converts a hue in degrees, and saturation and value between 0 and 1, to RGB.
+pub fn hsv_to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let c = v * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [c, x, 0.0],
+        1 => [x, c, 0.0],
+        2 => [0.0, c, x],
+        3 => [0.0, x, c],
+        4 => [x, 0.0, c],
+        _ => [c, 0.0, x],
+    };
+    let m = v - c;
+    [r + m, g + m, b + m]
+}
+
+/// The inverse of [hsv_to_rgb]. Grays have a hue of 0.
+pub fn rgb_to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let c = max - min;
+    let h = if c == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / c).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / c + 2.0)
+    } else {
+        60.0 * ((r - g) / c + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { c / max };
+    [h, s, max]
+}
+
+/// Things that can be drawn in front of or behind each other, no matter
+/// the order they're pushed in. `depth` is between -1 and 1, and higher
+/// values are drawn on top. The default is 0.
+///
+/// Depth testing is only turned on for batches where something has a
+/// non-zero depth, and only works within a batch. Transparent pixels still
+/// hide what's behind them, so depth is best used for opaque sprites.
+/// Sorting by y in a top-down game is a good use.
+pub trait Depth {
+    fn depth_mut(&mut self) -> &mut f32;
+
+    impl_transform!(deref, depth, =, depth: f32 => depth_mut);
+}
+
+/// Things that can be skewed. The shear is applied after scaling but before rotation.
+pub trait Shear {
+    fn shear_mut(&mut self) -> &mut [f32; 2];
+
+    impl_transform!(arr, skew, =, kx: f32 => shear_mut[0], ky: f32 => shear_mut[1]);
 }
 
 /// From where you see the world. Can be moved around via [Transform].
+#[derive(Clone, Copy, Debug)]
 pub struct Camera {
     position: Vector2<f32>,
     scale: Vector2<f32>,
@@ -232,21 +417,478 @@ impl Camera {
             Matrix4::from_translation(Vector3::new(self.position.x, self.position.y, 0.0));
         scale * rotation * translation
     }
+
+    /// The inverse of [Camera::matrix]. Takes a point in normalized device
+    /// coordinates, where the screen goes from -1 to 1, and returns where
+    /// it is in the world.
+    pub fn screen_to_world(&self, x: f32, y: f32) -> (f32, f32) {
+        let (x, y) = (x / self.scale.x, y / self.scale.y);
+        let (sin, cos) = (-self.rotation).sin_cos();
+        (
+            x * cos - y * sin - self.position.x,
+            x * sin + y * cos - self.position.y,
+        )
+    }
+
+    /// The camera for a layer that moves `factor` times as fast as the world.
+    fn with_parallax(&self, factor: f32) -> Self {
+        Self {
+            position: self.position * factor,
+            ..*self
+        }
+    }
+
+    /// The camera moved to the closest point on the pixel grid.
+    fn snapped(&self, units_per_pixel: f32) -> Self {
+        if units_per_pixel <= 0.0 {
+            return *self;
+        }
+        let snap = |x: f32| (x / units_per_pixel).round() * units_per_pixel;
+        Self {
+            position: Vector2::new(snap(self.position.x), snap(self.position.y)),
+            ..*self
+        }
+    }
+}
+
+/// A camera that only draws to a part of the window.
+#[derive(Clone, Copy, Debug)]
+pub struct Viewport {
+    pub camera: Camera,
+    /// `[x, y, width, height]` as fractions of the window size,
+    /// starting from the bottom left. With a [logical size](Renderer::set_logical_size)
+    /// they're fractions of the letterboxed area instead.
+    pub rect: [f32; 4],
+}
+
+impl Viewport {
+    pub fn new(camera: Camera, rect: [f32; 4]) -> Self {
+        Self { camera, rect }
+    }
+
+    /// `outer` is the part of the window the viewport is placed in,
+    /// in the same format as [Viewport::rect].
+    fn gl_viewport(&self, size: (u32, u32), outer: [f32; 4]) -> GLViewport {
+        let [x, y, w, h] = self.rect;
+        let [ox, oy, ow, oh] = outer;
+        gl_viewport([ox + x * ow, oy + y * oh, w * ow, h * oh], size)
+    }
+}
+
+/// Converts `[x, y, width, height]` in fractions of the window to pixels.
+fn gl_viewport(rect: [f32; 4], (w, h): (u32, u32)) -> GLViewport {
+    GLViewport::Specific {
+        x: (rect[0] * w as f32) as u32,
+        y: (rect[1] * h as f32) as u32,
+        width: (rect[2] * w as f32) as u32,
+        height: (rect[3] * h as f32) as u32,
+    }
+}
+
+/// A frame that's being pushed to. Everything that hasn't been rendered is
+/// thrown away when it's dropped, so nothing leaks into the next frame,
+/// even if rendering fails.
+///
+/// Created with [Renderer::frame], and works just like the [Renderer].
+pub struct Frame<'a> {
+    renderer: &'a mut Renderer,
+}
+
+impl Deref for Frame<'_> {
+    type Target = Renderer;
+
+    fn deref(&self) -> &Self::Target {
+        self.renderer
+    }
+}
+
+impl DerefMut for Frame<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.renderer
+    }
+}
+
+impl Drop for Frame<'_> {
+    fn drop(&mut self) {
+        self.renderer.clear();
+    }
+}
+
+/// Something to render to instead of the screen. What's rendered ends up in
+/// a sprite sheet, so it can be drawn with [Sprite]s.
+///
+/// Created with [Renderer::add_render_target].
+pub struct RenderTarget {
+    /// From where the target sees the world.
+    pub camera: Camera,
+    framebuffer: Framebuffer<GLVer, Dim2, NormRGBA8UI, Depth32F>,
+    sheet: SpriteSheetID,
+}
+
+impl RenderTarget {
+    /// The sprite sheet that holds the result.
+    pub fn sheet(&self) -> SpriteSheetID {
+        self.sheet
+    }
+}
+
+/// A [Transform] that can be attached to a parent. Moving, rotating or scaling
+/// the parent also moves, rotates and scales the child.
+///
+/// ```ignore
+/// let body = Rc::new(RefCell::new(TransformNode::new()));
+/// let mut weapon = TransformNode::new();
+/// weapon.set_parent(Some(Rc::clone(&body)));
+/// weapon.at(0.5, 0.0);
+///
+/// body.borrow_mut().rotate(0.1);
+/// renderer.push_relative(&weapon, Sprite::new(region));
+/// ```
+#[derive(Clone, Debug)]
+pub struct TransformNode {
+    position: Vector2<f32>,
+    scale: Vector2<f32>,
+    rotation: f32,
+    parent: Option<Rc<RefCell<TransformNode>>>,
+}
+
+impl_transform_for!(TransformNode);
+
+impl TransformNode {
+    pub fn new() -> Self {
+        Self {
+            position: Vector2::new(0.0, 0.0),
+            scale: Vector2::new(1.0, 1.0),
+            rotation: 0.0,
+            parent: None,
+        }
+    }
+
+    pub fn parent(&self) -> Option<&Rc<RefCell<TransformNode>>> {
+        self.parent.as_ref()
+    }
+
+    pub fn set_parent(&mut self, parent: Option<Rc<RefCell<TransformNode>>>) {
+        self.parent = parent;
+    }
+
+    /// The transform of this node, ignoring the parents.
+    pub fn local_matrix(&self) -> cgmath::Matrix4<f32> {
+        use cgmath::{Matrix4, Rad, Vector3};
+        let scale = Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, 1.0);
+        let rotation = Matrix4::from_angle_z(Rad(self.rotation));
+        let translation =
+            Matrix4::from_translation(Vector3::new(self.position.x, self.position.y, 0.0));
+        translation * rotation * scale
+    }
+
+    /// The transform of this node with all the parents applied.
+    pub fn world_matrix(&self) -> cgmath::Matrix4<f32> {
+        match &self.parent {
+            Some(parent) => parent.borrow().world_matrix() * self.local_matrix(),
+            None => self.local_matrix(),
+        }
+    }
+
+    /// The total rotation of this node with all the parents applied.
+    pub fn world_rotation(&self) -> f32 {
+        match &self.parent {
+            Some(parent) => parent.borrow().world_rotation() + self.rotation,
+            None => self.rotation,
+        }
+    }
+
+    /// The total scale of this node with all the parents applied.
+    pub fn world_scale(&self) -> Vector2<f32> {
+        match &self.parent {
+            Some(parent) => {
+                let parent = parent.borrow().world_scale();
+                Vector2::new(parent.x * self.scale.x, parent.y * self.scale.y)
+            }
+            None => self.scale,
+        }
+    }
 }
 
 type ShaderProgram = Program<GLVer, VertexSemantics, (), ShaderInterface>;
 
+fn build_program(context: &mut GL33Surface, vs: &str, fs: &str) -> Result<ShaderProgram, ProgramError> {
+    context
+        .new_shader_program::<VertexSemantics, (), ShaderInterface>()
+        .from_strings(vs, None, None, fs)
+        .map(|program| program.ignore_warnings())
+}
+
+/// The shader files on disk, watched for changes in debug builds.
+/// See [Renderer::reload].
+#[cfg(debug_assertions)]
+struct ShaderFiles {
+    /// The vertex, fragment and particle vertex shaders.
+    files: [LoadedFile; 3],
+    sources: [String; 3],
+}
+
+#[cfg(debug_assertions)]
+impl ShaderFiles {
+    /// Returns None if the source code isn't around, like when the game is
+    /// moved to another computer.
+    fn open() -> Option<Self> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+        let paths = ["vs.glsl", "fs.glsl", "vs_particle.glsl"];
+        if !paths.iter().all(|path| dir.join(path).exists()) {
+            return None;
+        }
+        let open = |path: &str| LoadedFile::new(dir.join(path)).0;
+        Some(Self {
+            files: [open(paths[0]), open(paths[1]), open(paths[2])],
+            sources: [VS_STR.to_string(), FS_STR.to_string(), VS_PARTICLE_STR.to_string()],
+        })
+    }
+}
+
+// Tesses that are kept between frames, one per batch. The instance buffers
+// are updated in place, and only reallocated when they need to grow.
+macro_rules! tess_pool {
+    ($name:ident, $instance:ty) => {
+        struct $name {
+            tesses: Vec<Option<(usize, Tess<GLVer, Vertex, (), $instance, Interleaved>)>>,
+        }
+
+        impl $name {
+            fn new() -> Self {
+                Self {
+                    tesses: Vec::new(),
+                }
+            }
+
+            /// Copies the instances to the i:th tess, growing it if needed.
+            /// Returns true if a new tess was built.
+            fn upload(&mut self, context: &mut GL33Surface, i: usize, instances: &[$instance]) -> bool {
+                if instances.is_empty() {
+                    return false;
+                }
+                if self.tesses.len() <= i {
+                    self.tesses.resize_with(i + 1, || None);
+                }
+                match &mut self.tesses[i] {
+                    Some((capacity, tess)) if *capacity >= instances.len() => {
+                        tess.instances_mut().unwrap()[..instances.len()].copy_from_slice(instances);
+                        false
+                    }
+                    slot => {
+                        let capacity = instances.len().next_power_of_two();
+                        let mut data = instances.to_vec();
+                        data.resize(capacity, instances[0]);
+                        let tess = context
+                            .new_tess()
+                            .set_vertices(&RECT[..])
+                            .set_instances(data)
+                            .set_mode(Mode::Triangle)
+                            .build()
+                            .unwrap();
+                        *slot = Some((capacity, tess));
+                        true
+                    }
+                }
+            }
+
+            fn get(&self, i: usize) -> Option<&Tess<GLVer, Vertex, (), $instance, Interleaved>> {
+                self.tesses.get(i).and_then(|t| t.as_ref()).map(|(_, tess)| tess)
+            }
+        }
+    };
+}
+
+tess_pool!(InstanceTessPool, Instance);
+tess_pool!(ParticleTessPool, Particle);
+
+/// Like the tess pools, but for triangle strips without instances.
+struct TrailTessPool {
+    tesses: Vec<Option<(usize, Tess<GLVer, TrailVertex, (), (), Interleaved>)>>,
+}
+
+impl TrailTessPool {
+    fn new() -> Self {
+        Self {
+            tesses: Vec::new(),
+        }
+    }
+
+    /// Copies the vertices to the i:th tess, growing it if needed.
+    /// Returns true if a new tess was built.
+    fn upload(&mut self, context: &mut GL33Surface, i: usize, vertices: &[TrailVertex]) -> bool {
+        if vertices.is_empty() {
+            return false;
+        }
+        if self.tesses.len() <= i {
+            self.tesses.resize_with(i + 1, || None);
+        }
+        match &mut self.tesses[i] {
+            Some((capacity, tess)) if *capacity >= vertices.len() => {
+                tess.vertices_mut().unwrap()[..vertices.len()].copy_from_slice(vertices);
+                false
+            }
+            slot => {
+                let capacity = vertices.len().next_power_of_two();
+                let mut data = vertices.to_vec();
+                data.resize(capacity, vertices[0]);
+                let tess = context
+                    .new_tess()
+                    .set_vertices(data)
+                    .set_mode(Mode::TriangleStrip)
+                    .build()
+                    .unwrap();
+                *slot = Some((capacity, tess));
+                true
+            }
+        }
+    }
+
+    fn get(&self, i: usize) -> Option<&Tess<GLVer, TrailVertex, (), (), Interleaved>> {
+        self.tesses.get(i).and_then(|t| t.as_ref()).map(|(_, tess)| tess)
+    }
+}
+
+/// What the last call to [Renderer::render] did. See [Renderer::stats].
+#[derive(Copy, Clone, Debug, Default)]
+pub struct RenderStats {
+    /// Not counting text.
+    pub draw_calls: usize,
+    pub total_instances: usize,
+    pub total_particles: usize,
+    /// How many instance buffers had to be created or grown.
+    pub tess_built: usize,
+}
+
+/// How a batch is combined with what is already on the screen.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BlendMode {
+    /// Regular transparency, the default.
+    Alpha,
+    /// Adds the colors together. Great for fire and light.
+    Additive,
+    /// Multiplies the colors together. Good for shadows.
+    Multiply,
+    /// Overwrites what's already there, ignoring alpha.
+    None,
+    /// Transparency for sprite sheets with [premultiplied](Image::premultiply_alpha)
+    /// alpha. Colors should be premultiplied too.
+    PremultipliedAlpha,
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Alpha
+    }
+}
+
+impl BlendMode {
+    fn blending(self) -> Option<Blending> {
+        match self {
+            BlendMode::Alpha => Some(Blending {
+                equation: Equation::Additive,
+                src: Factor::SrcAlpha,
+                dst: Factor::SrcAlphaComplement,
+            }),
+            BlendMode::Additive => Some(Blending {
+                equation: Equation::Additive,
+                src: Factor::SrcAlpha,
+                dst: Factor::One,
+            }),
+            BlendMode::Multiply => Some(Blending {
+                equation: Equation::Additive,
+                src: Factor::DestColor,
+                dst: Factor::Zero,
+            }),
+            BlendMode::None => None,
+            BlendMode::PremultipliedAlpha => Some(Blending {
+                equation: Equation::Additive,
+                src: Factor::One,
+                dst: Factor::SrcAlphaComplement,
+            }),
+        }
+    }
+
+    fn render_state(self) -> RenderState {
+        let state = RenderState::default().set_depth_test(None);
+        match self.blending() {
+            Some(blending) => state.set_blending(blending),
+            None => state,
+        }
+    }
+}
+
+/// All the render state that has to be the same for instances
+/// to be drawn in the same draw call.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct BatchKey {
+    pub blend_mode: BlendMode,
+    /// Higher layers are drawn on top of lower layers.
+    pub layer: i32,
+}
+
+/// What a [Batch] draws.
+pub enum BatchContents {
+    Instances(Vec<Instance>),
+    Particles(FrozenParticles),
+    Trail(Vec<TrailVertex>),
+}
+
+/// Something that is drawn with one draw call. The batches are drawn in order.
+/// Used internally.
+pub struct Batch {
+    pub key: BatchKey,
+    pub contents: BatchContents,
+}
+
 /// A big struct holding all the rendering state.
 pub struct Renderer {
+    /// Draws to the whole window, unless there are [Renderer::viewports].
     pub camera: Camera,
-    pub instances: Vec<Vec<Instance>>,
-    pub particles: Vec<FrozenParticles>,
+    /// Cameras that each draw the whole scene to a part of the window, in order.
+    /// Useful for split-screen and minimaps. [Renderer::camera] is ignored
+    /// if this isn't empty.
+    pub viewports: Vec<Viewport>,
+    /// The size of a pixel in world units, if snapping is enabled.
+    /// See [Renderer::pixel_perfect].
+    pixel_snap: f32,
+    /// See [Renderer::set_linear_color].
+    linear_color: bool,
+    /// See [Renderer::set_logical_size].
+    logical_size: Option<(u32, u32)>,
+    /// See [Renderer::set_layer_parallax].
+    parallax: HashMap<i32, f32>,
+    /// The state used for everything pushed from now on.
+    pub key: BatchKey,
+    pub batches: Vec<Batch>,
     pub tex: Tex,
+    /// The size of [Renderer::tex]. See [Renderer::set_sprite_sheet_size].
+    sprite_sheet_size: [u32; 3],
+    sampler: Sampler,
     pub sprite_sheets: Vec<SpriteSheet>,
     pub font: GlyphBrush<GLVer>,
+    /// Set by [Renderer::push_text], see [Renderer::preload_glyphs].
+    text_queued: bool,
 
     pub sprite_program: ShaderProgram,
     pub particle_program: ShaderProgram,
+
+    /// Drawn on top of everything in screen space, ignoring the camera.
+    overlay: Vec<Instance>,
+    /// See [Renderer::set_screen_tint].
+    screen_tint: [f32; 4],
+
+    stats: RenderStats,
+
+    sprite_tess: InstanceTessPool,
+    particle_tess: ParticleTessPool,
+    trail_tess: TrailTessPool,
+    overlay_tess: InstanceTessPool,
+
+    #[cfg(debug_assertions)]
+    shader_files: Option<ShaderFiles>,
+    /// See [Renderer::shader_error].
+    shader_error: Option<String>,
 }
 
 /// If something can be rendered, it has to be Stamp.
@@ -257,10 +899,12 @@ pub trait Stamp {
 /// A rectangle that can be rendered to the screen.
 #[derive(Clone, Copy, Debug)]
 pub struct Rect {
-    position: Vector2<f32>,
-    scale: Vector2<f32>,
+    pub(crate) position: Vector2<f32>,
+    pub(crate) scale: Vector2<f32>,
     rotation: f32,
+    shear: [f32; 2],
     color: [f32; 4],
+    depth: f32,
 }
 
 impl_transform_for!(Rect);
@@ -269,6 +913,22 @@ impl Tint for Rect {
     fn color_mut(&mut self) -> &mut [f32; 4] {
         &mut self.color
     }
+
+    fn color(&self) -> [f32; 4] {
+        self.color
+    }
+}
+
+impl Shear for Rect {
+    fn shear_mut(&mut self) -> &mut [f32; 2] {
+        &mut self.shear
+    }
+}
+
+impl Depth for Rect {
+    fn depth_mut(&mut self) -> &mut f32 {
+        &mut self.depth
+    }
 }
 
 impl Stamp for &Rect {
@@ -278,8 +938,11 @@ impl Stamp for &Rect {
             rotation: IRotation::new(self.rotation),
             scale: IScale::new(self.scale.into()),
             color: IColor::new(self.color),
+            // No sheet, so the uv is ignored.
             sheet: ISheet::new(-1.0),
             uv: IUV::new([0.0, 0.0, 1.0, 1.0]),
+            shear: IShear::new(self.shear),
+            depth: IDepth::new(self.depth),
         }
     }
 }
@@ -304,7 +967,9 @@ impl Rect {
             position: Vector2::new(0.0, 0.0),
             scale: Vector2::new(1.0, 1.0),
             rotation: 0.0,
+            shear: [0.0, 0.0],
             color: [1.0, 1.0, 1.0, 1.0],
+            depth: 0.0,
         }
     }
 }
@@ -312,12 +977,14 @@ impl Rect {
 /// A rectangle that has a nice image on it.
 #[derive(Clone, Copy, Debug)]
 pub struct Sprite {
-    position: Vector2<f32>,
-    scale: Vector2<f32>,
+    pub(crate) position: Vector2<f32>,
+    pub(crate) scale: Vector2<f32>,
     rotation: f32,
+    shear: [f32; 2],
     color: [f32; 4],
     sheet: f32,
     rect: [f32; 4],
+    depth: f32,
 }
 
 impl_transform_for!(Sprite);
@@ -326,6 +993,22 @@ impl Tint for Sprite {
     fn color_mut(&mut self) -> &mut [f32; 4] {
         &mut self.color
     }
+
+    fn color(&self) -> [f32; 4] {
+        self.color
+    }
+}
+
+impl Shear for Sprite {
+    fn shear_mut(&mut self) -> &mut [f32; 2] {
+        &mut self.shear
+    }
+}
+
+impl Depth for Sprite {
+    fn depth_mut(&mut self) -> &mut f32 {
+        &mut self.depth
+    }
 }
 
 impl Stamp for &Sprite {
@@ -337,6 +1020,8 @@ impl Stamp for &Sprite {
             color: IColor::new(self.color),
             sheet: ISheet::new(self.sheet),
             uv: IUV::new(self.rect),
+            shear: IShear::new(self.shear),
+            depth: IDepth::new(self.depth),
         }
     }
 }
@@ -362,9 +1047,68 @@ impl Sprite {
             position: Vector2::new(0.0, 0.0),
             scale: Vector2::new(1.0, 1.0),
             rotation: 0.0,
+            shear: [0.0, 0.0],
             color: [1.0, 1.0, 1.0, 1.0],
             sheet: region.0,
             rect: region.1,
+            depth: 0.0,
+        }
+    }
+
+    /// Moves the region across the sheet, in fractions of the sheet.
+    /// With a repeating sampler, see [Game::set_texture_wrap](crate::Game::set_texture_wrap),
+    /// this scrolls a tiling background forever.
+    pub fn scroll_uv(&mut self, du: f32, dv: f32) -> &mut Self {
+        self.rect[0] += du;
+        self.rect[1] += dv;
+        self.rect[2] += du;
+        self.rect[3] += dv;
+        self
+    }
+
+    /// Shows the region `x` times horizontally and `y` times vertically.
+    /// Needs a repeating sampler, and only tiles seamlessly if the region
+    /// covers the whole sheet.
+    pub fn repeat_uv(&mut self, x: f32, y: f32) -> &mut Self {
+        let [u0, v0, u1, v1] = self.rect;
+        self.rect = [u0, v0, u0 + (u1 - u0) * x, v0 + (v1 - v0) * y];
+        self
+    }
+}
+
+/// A sprite that can be stretched without distorting its borders.
+/// Great for UI panels.
+///
+/// The region is split into a 3x3 grid. The corners keep their size,
+/// the edges are stretched along one axis and the center along both.
+#[derive(Clone, Copy, Debug)]
+pub struct NineSlice {
+    pub region: SpriteRegion,
+    /// How much of the region the borders take up, as fractions of the
+    /// region: `[left, right, bottom, top]`.
+    pub insets: [f32; 4],
+    /// How large the borders are when drawn: `[left, right, bottom, top]`.
+    pub border: [f32; 4],
+    pub color: [f32; 4],
+}
+
+impl Tint for NineSlice {
+    fn color_mut(&mut self) -> &mut [f32; 4] {
+        &mut self.color
+    }
+
+    fn color(&self) -> [f32; 4] {
+        self.color
+    }
+}
+
+impl NineSlice {
+    pub fn new(region: SpriteRegion, insets: [f32; 4], border: [f32; 4]) -> Self {
+        Self {
+            region,
+            insets,
+            border,
+            color: [1.0, 1.0, 1.0, 1.0],
         }
     }
 }
@@ -374,27 +1118,26 @@ impl Renderer {
     pub fn new(context: &mut GL33Surface, sampler: Sampler) -> Self {
 
         // Setup shader programs.
-        let sprite_program = context
-            .new_shader_program::<VertexSemantics, (), ShaderInterface>()
-            .from_strings(VS_STR, None, None, FS_STR)
-            .unwrap()
-            .ignore_warnings();
-
-        let particle_program = context
-            .new_shader_program::<VertexSemantics, (), ShaderInterface>()
-            .from_strings(VS_PARTICLE_STR, None, None, FS_STR)
-            .unwrap()
-            .ignore_warnings();
+        let sprite_program = build_program(context, VS_STR, FS_STR).unwrap();
+        let particle_program = build_program(context, VS_PARTICLE_STR, FS_STR).unwrap();
 
         let tex: Tex =
             Texture::new(context, SPRITE_SHEET_SIZE, 0, sampler).expect("failed to create texture");
 
         Self {
             camera: Camera::new(),
-            instances: vec![Vec::new()],
+            viewports: Vec::new(),
+            pixel_snap: 0.0,
+            linear_color: false,
+            logical_size: None,
+            parallax: HashMap::new(),
+            key: BatchKey::default(),
+            batches: Vec::new(),
             tex,
+            sprite_sheet_size: SPRITE_SHEET_SIZE,
+            sampler,
             sprite_sheets: Vec::new(),
-            particles: Vec::new(),
+            text_queued: false,
             font: GlyphBrushBuilder::using_font(
                 // We forcefully include a default font,
                 // if you don't load any yourself.
@@ -404,143 +1147,992 @@ impl Renderer {
 
             sprite_program,
             particle_program,
+
+            overlay: Vec::new(),
+            screen_tint: [0.0, 0.0, 0.0, 0.0],
+
+            stats: RenderStats::default(),
+
+            sprite_tess: InstanceTessPool::new(),
+            particle_tess: ParticleTessPool::new(),
+            trail_tess: TrailTessPool::new(),
+            overlay_tess: InstanceTessPool::new(),
+
+            #[cfg(debug_assertions)]
+            shader_files: ShaderFiles::open(),
+            shader_error: None,
         }
     }
 
+    /// Starts a new frame, see [Frame].
+    ///
+    /// ```ignore
+    /// let mut frame = renderer.frame();
+    /// frame.push(sprite);
+    /// frame.render(context)?;
+    /// ```
+    pub fn frame(&mut self) -> Frame<'_> {
+        Frame { renderer: self }
+    }
+
+    /// Throws away everything that has been pushed since the last render.
+    pub fn clear(&mut self) {
+        self.batches.clear();
+        self.overlay.clear();
+        self.text_queued = false;
+    }
+
     /// Queues the stamp for rendering.
     pub fn push<T: Stamp>(&mut self, stamp: T) {
-        self.instances.last_mut().unwrap().push(stamp.stamp());
+        self.current_instances().push(stamp.stamp());
+    }
+
+    /// Queues the stamp for rendering, placed relative to the node.
+    ///
+    /// Rotation and scale are added on top of the stamp's own, so a
+    /// non-uniformly scaled parent doesn't skew its children.
+    pub fn push_relative<T: Stamp>(&mut self, node: &TransformNode, stamp: T) {
+        use cgmath::Vector4;
+        let mut instance = stamp.stamp();
+
+        let position = node.world_matrix()
+            * Vector4::new(instance.position[0], instance.position[1], 0.0, 1.0);
+        let scale = node.world_scale();
+        instance.position = IPosition::new([position.x, position.y]);
+        instance.rotation = IRotation::new(*instance.rotation + node.world_rotation());
+        instance.scale = IScale::new([instance.scale[0] * scale.x, instance.scale[1] * scale.y]);
+
+        self.current_instances().push(instance);
+    }
+
+    /// Queues a border around the outside of the rect, `thickness` world
+    /// units wide. Made from four thin rects that follow the rect's position,
+    /// rotation and depth, but not its shear.
+    pub fn push_rect_outline(&mut self, rect: &Rect, thickness: f32, color: [f32; 4]) {
+        let (w, h) = (rect.scale.x.abs(), rect.scale.y.abs());
+        let (sin, cos) = rect.rotation.sin_cos();
+        // The top and bottom cover the corners.
+        let sides = [
+            ([0.0, (h + thickness) / 2.0], [w + 2.0 * thickness, thickness]),
+            ([0.0, -(h + thickness) / 2.0], [w + 2.0 * thickness, thickness]),
+            ([-(w + thickness) / 2.0, 0.0], [thickness, h]),
+            ([(w + thickness) / 2.0, 0.0], [thickness, h]),
+        ];
+        for &([ox, oy], [sw, sh]) in sides.iter() {
+            let mut side = Rect::new();
+            side.at(
+                rect.position.x + ox * cos - oy * sin,
+                rect.position.y + ox * sin + oy * cos,
+            )
+            .scale(sw, sh)
+            .angle(rect.rotation)
+            .depth(rect.depth);
+            *side.color_mut() = color;
+            self.push(side);
+        }
+    }
+
+    /// Queues a [NineSlice] for rendering, centered on `(x, y)` and
+    /// stretched to `(w, h)`.
+    pub fn push_nine_slice(&mut self, slice: &NineSlice, x: f32, y: f32, w: f32, h: f32) {
+        let (sheet, [u0, v0, u1, v1]) = slice.region;
+        let [left, right, bottom, top] = slice.border;
+        let [il, ir, ib, it] = slice.insets;
+        let (du, dv) = (u1 - u0, v1 - v0);
+
+        let xs = [x - w / 2.0, x - w / 2.0 + left, x + w / 2.0 - right, x + w / 2.0];
+        let ys = [y - h / 2.0, y - h / 2.0 + bottom, y + h / 2.0 - top, y + h / 2.0];
+        let us = [u0, u0 + il * du, u1 - ir * du, u1];
+        let vs = [v0, v0 + ib * dv, v1 - it * dv, v1];
+
+        for i in 0..3 {
+            for j in 0..3 {
+                let mut sprite = Sprite::new((sheet, [us[i], vs[j], us[i + 1], vs[j + 1]]));
+                sprite
+                    .at((xs[i] + xs[i + 1]) / 2.0, (ys[j] + ys[j + 1]) / 2.0)
+                    .scale(xs[i + 1] - xs[i], ys[j + 1] - ys[j]);
+                *sprite.color_mut() = slice.color;
+                self.push(sprite);
+            }
+        }
+    }
+
+    /// Queues text drawn with a [BitmapFont], with the top left corner at `(x, y)`.
+    /// Unlike [Renderer::push_text] it's drawn with the camera, like any other sprite.
+    pub fn push_bitmap_text(&mut self, font: &BitmapFont, text: &str, x: f32, y: f32) {
+        let sprites = font.sprites(&self.sprite_sheets[font.sheet], text, x, y);
+        self.current_instances().extend(sprites.into_iter().map(Stamp::stamp));
+    }
+
+    /// Queues the trail for rendering.
+    ///
+    /// Like particle systems, the trail gets a batch of its own.
+    pub fn push_trail(&mut self, trail: &Trail) {
+        self.batches.push(Batch {
+            key: self.key,
+            contents: BatchContents::Trail(trail.vertices()),
+        });
     }
 
     /// Queues the particle_systems for rendering.
+    ///
+    /// The particle system gets a batch of its own, so it's drawn after
+    /// everything that was pushed before it.
     pub fn push_particle_system(&mut self, system: &ParticleSystem) {
-        self.particles.push(system.freeze());
-        self.instances.push(Vec::new());
+        self.batches.push(Batch {
+            key: self.key,
+            contents: BatchContents::Particles(system.freeze()),
+        });
+    }
+
+    /// The blend mode used for everything pushed from now on.
+    pub fn blend_mode(&self) -> BlendMode {
+        self.key.blend_mode
+    }
+
+    /// Changes how everything pushed from now on is blended. Things
+    /// that are already pushed keep their blend mode.
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.key.blend_mode = blend_mode;
+    }
+
+    /// The layer used for everything pushed from now on.
+    pub fn layer(&self) -> i32 {
+        self.key.layer
+    }
+
+    /// Everything pushed from now on, particle systems included, is drawn
+    /// on top of lower layers and below higher layers. Within a layer things
+    /// are drawn in the order they're pushed. The default layer is 0.
+    ///
+    /// Smoke can be drawn both behind and in front of a character by pushing
+    /// it on different layers.
+    pub fn set_layer(&mut self, layer: i32) {
+        self.key.layer = layer;
+    }
+
+    /// How much a layer follows the camera's position. 1 moves with the
+    /// world, which is the default, and 0 is fixed to the screen. Backgrounds
+    /// with something in between move slower than the foreground, which
+    /// gives a sense of depth. Rotation and zoom aren't affected.
+    pub fn set_layer_parallax(&mut self, layer: i32, factor: f32) {
+        self.parallax.insert(layer, factor);
+    }
+
+    pub fn layer_parallax(&self, layer: i32) -> f32 {
+        self.parallax.get(&layer).copied().unwrap_or(1.0)
+    }
+
+    /// Finds the instances to push to, given the current [BatchKey].
+    ///
+    /// Instances are merged into the last batch if it has the same key and
+    /// holds instances. Otherwise a new batch is started.
+    fn current_instances(&mut self) -> &mut Vec<Instance> {
+        let key = self.key;
+        if !matches!(
+            self.batches.last(),
+            Some(Batch { key: k, contents: BatchContents::Instances(_) }) if *k == key
+        ) {
+            self.batches.push(Batch {
+                key,
+                contents: BatchContents::Instances(Vec::new()),
+            });
+        }
+        match &mut self.batches.last_mut().unwrap().contents {
+            BatchContents::Instances(instances) => instances,
+            BatchContents::Particles(_) | BatchContents::Trail(_) => unreachable!(),
+        }
     }
 
     /// Registers an image as a new sprite sheet with the specified tile size.
     ///
     /// There's a hard limit on the number of SpriteSheets that can be
-    /// added: see [SPRITE_SHEET_SIZE].
+    /// added, and on how large they can be: see [Renderer::sprite_sheet_size].
     pub fn add_sprite_sheet(&mut self, image: Image, tile_size: (Pixels, Pixels)) -> SpriteSheetID {
         let id = self.sprite_sheets.len();
-        assert!((id as u32) < SPRITE_SHEET_SIZE[2]);
+        let [w, h, layers] = self.sprite_sheet_size;
+        assert!((id as u32) < layers, "too many sprite sheets, the limit is {}", layers);
+        assert!(
+            image.width as u32 <= w && image.height as u32 <= h,
+            "a {}x{} sprite sheet doesn't fit in {}x{}, see Renderer::set_sprite_sheet_size",
+            image.width, image.height, w, h,
+        );
+        assert!(
+            tile_size.0 > 0 && tile_size.1 > 0
+                && image.width % tile_size.0 == 0 && image.height % tile_size.1 == 0,
+            "the tile size {:?} doesn't evenly divide the {}x{} sprite sheet",
+            tile_size, image.width, image.height,
+        );
 
         // Upload texture to slot
         let sheet = SpriteSheet {
             id,
             image,
             tile_size,
+            sheet_size: self.sprite_sheet_size,
+            half_texel_inset: false,
         };
         sheet.upload(&mut self.tex);
         self.sprite_sheets.push(sheet);
         id
     }
 
+    /// Replaces the image of a sprite sheet, keeping its ID and tile size,
+    /// so regions from it stay valid. Good for images that are regenerated
+    /// while the game is running.
+    pub fn update_sprite_sheet(&mut self, id: SpriteSheetID, image: Image) {
+        let [w, h, _] = self.sprite_sheet_size;
+        assert!(
+            image.width as u32 <= w && image.height as u32 <= h,
+            "a {}x{} sprite sheet doesn't fit in {}x{}, see Renderer::set_sprite_sheet_size",
+            image.width, image.height, w, h,
+        );
+        let sheet = &mut self.sprite_sheets[id];
+        let tile_size = sheet.tile_size;
+        assert!(
+            image.width % tile_size.0 == 0 && image.height % tile_size.1 == 0,
+            "the tile size {:?} doesn't evenly divide the {}x{} sprite sheet",
+            tile_size, image.width, image.height,
+        );
+
+        // Don't leave parts of a larger old image behind.
+        let (old_w, old_h) = sheet.dimensions();
+        if old_w > image.width || old_h > image.height {
+            self.tex
+                .upload_part_raw(
+                    GenMipmaps::No,
+                    [0, 0, id as u32],
+                    [old_w as u32, old_h as u32, 1],
+                    &vec![0; old_w * old_h * 4],
+                )
+                .unwrap();
+        }
+        sheet.image = image;
+        sheet.upload(&mut self.tex);
+    }
+
+    /// The tile size the sprite sheet was added with.
+    pub fn sprite_sheet_tile_size(&self, id: SpriteSheetID) -> (Pixels, Pixels) {
+        self.sprite_sheets[id].tile_size()
+    }
+
+    /// The size of the sprite sheet's image, in pixels.
+    pub fn sprite_sheet_dimensions(&self, id: SpriteSheetID) -> (Pixels, Pixels) {
+        self.sprite_sheets[id].dimensions()
+    }
+
+    /// The maximum width and height of a sprite sheet, and how many there can be.
+    pub fn sprite_sheet_size(&self) -> [u32; 3] {
+        self.sprite_sheet_size
+    }
+
+    /// Recreates the sprite sheet texture with room for larger or more sprite
+    /// sheets, and uploads the existing ones again. The texture takes
+    /// `width * height * layers * 4` bytes of GPU memory.
+    pub fn set_sprite_sheet_size(&mut self, context: &mut GL33Surface, size: [u32; 3]) {
+        assert!(self.sprite_sheets.len() as u32 <= size[2]);
+        self.sprite_sheet_size = size;
+        self.recreate_texture(context);
+    }
+
+    /// How the sprite sheets are filtered. [Game](crate::Game) uses nearest
+    /// filtering, which is good for pixel art. Use linear filtering for smooth
+    /// sprites.
+    pub fn sampler(&self) -> &Sampler {
+        &self.sampler
+    }
+
+    /// Recreates the sprite sheet texture with the new sampler, and uploads
+    /// the existing sprite sheets again.
+    pub fn set_sampler(&mut self, context: &mut GL33Surface, sampler: Sampler) {
+        self.sampler = sampler;
+        self.recreate_texture(context);
+    }
+
+    fn recreate_texture(&mut self, context: &mut GL33Surface) {
+        let size = self.sprite_sheet_size;
+        self.tex = Texture::new(context, size, 0, self.sampler).expect("failed to create texture");
+        for sheet in self.sprite_sheets.iter_mut() {
+            assert!(sheet.image.width as u32 <= size[0] && sheet.image.height as u32 <= size[1]);
+            sheet.sheet_size = size;
+            sheet.upload(&mut self.tex);
+        }
+    }
+
     pub fn add_font(&mut self, font: Font) -> FontId {
         self.font.add_font(font.font)
     }
 
+    /// Screen positions are in pixels from the top left of the window,
+    /// which on high-DPI screens isn't the same as the mouse position.
     pub fn push_text(&mut self, section: Section) {
         self.font.queue(section);
+        self.text_queued = true;
+    }
+
+    /// Puts the glyphs of the text in the glyph cache, without drawing them,
+    /// so there isn't a hitch the first time the text is drawn. `size` is
+    /// the scale the text will be drawn at.
+    ///
+    /// Preloading goes through the text queue, so it has to happen before any
+    /// text is pushed for the frame. Otherwise nothing is preloaded, so that
+    /// the pushed text isn't thrown away, and false is returned.
+    pub fn preload_glyphs(&mut self, context: &mut GL33Surface, text: &str, size: f32) -> bool {
+        if self.text_queued {
+            return false;
+        }
+        self.font.queue(Section::default().add_text(Text::new(text).with_scale(size)));
+        self.font.process_queued(context);
+        true
+    }
+
+    /// Shows FPS, a graph of the recent frame times and the slowest counters
+    /// in the corner of the screen. Doesn't care about the camera.
+    ///
+    /// Has to be called every frame it should be visible.
+    pub fn draw_debug_overlay(&mut self, collector: &Collector) {
+        // Number of counters to show.
+        const TOP_COUNTERS: usize = 5;
+        // The frame time that fills the graph, in seconds.
+        const GRAPH_MAX_TIME: f64 = 1.0 / 30.0;
+        // The size of the graph in screen space.
+        const GRAPH_SIZE: [f32; 2] = [0.5, 0.2];
+
+        let mut text = format!(
+            "FPS: {:.0}\nframe: {:.2} ms (min {:.2}, max {:.2})\n",
+            1.0 / collector.weighted_frame_time(),
+            collector.last_frame_time() * 1000.0,
+            collector.min_frame_time() * 1000.0,
+            collector.max_frame_time() * 1000.0,
+        );
+        let mut counters: Vec<_> = collector.counters().collect();
+        counters.sort_by(|a, b| {
+            b.average_time_this_frame()
+                .partial_cmp(&a.average_time_this_frame())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        for counter in counters.iter().take(TOP_COUNTERS) {
+            text += &format!(
+                "{} ({}:{}): {:.3} ms\n",
+                counter.name(),
+                counter.file(),
+                counter.line(),
+                counter.average_time_this_frame() * 1000.0,
+            );
+        }
+        if let Some(err) = &self.shader_error {
+            text += err;
+            text += "\n";
+        }
+        self.push_text(
+            Section::default()
+                .with_screen_position((10.0, 10.0))
+                .add_text(Text::new(&text).with_color([1.0, 1.0, 1.0, 1.0]).with_scale(20.0)),
+        );
+
+        let w = GRAPH_SIZE[0] / (crate::performance::FRAME_HISTORY as f32);
+        for (i, time) in collector.frame_times().enumerate() {
+            let fill = (time / GRAPH_MAX_TIME).min(1.0) as f32;
+            let h = fill * GRAPH_SIZE[1];
+            let mut bar = Rect::new();
+            bar.scale(w, h)
+                .at(-1.0 + w * (i as f32 + 0.5), -1.0 + h / 2.0)
+                .rgb(fill, 1.0 - fill, 0.0);
+            self.overlay.push(bar.stamp());
+        }
+    }
+
+    /// Blends an RGBA-color over the whole window, on top of everything
+    /// but text and the debug overlay. Doesn't care about the camera.
+    ///
+    /// There's no post-processing pass, so the tint is a rect drawn once over
+    /// the whole window after all [viewports](Renderer::viewports), letterbox
+    /// bars included. It's always alpha blended, whatever the blend mode is.
+    ///
+    /// Stays until it's changed, set the alpha to 0 to remove it.
+    pub fn set_screen_tint(&mut self, tint: [f32; 4]) {
+        self.screen_tint = tint;
+    }
+
+    pub fn screen_tint(&self) -> [f32; 4] {
+        self.screen_tint
+    }
+
+    /// Fades the screen to an RGB-color. An `alpha` of 0 shows the scene
+    /// and 1 only the color, great for scene transitions.
+    pub fn fade(&mut self, color: [f32; 3], alpha: f32) {
+        self.set_screen_tint([color[0], color[1], color[2], alpha.max(0.0).min(1.0)]);
     }
 
     /// Reload all assets that the renderer owns.
     ///
-    /// Currently this means as sprite sheets.
-    pub fn reload(&mut self) {
+    /// Currently this means sprite sheets, that are uploaded again if
+    /// their files or pixels have changed, and in debug builds the shaders
+    /// in lingon's source directory. If a shader doesn't compile the old
+    /// program is kept, and the error is returned and shown on the debug
+    /// overlay until a reload succeeds.
+    pub fn reload(&mut self, context: &mut GL33Surface) -> Result<(), String> {
         for sheet in self.sprite_sheets.iter_mut() {
             sheet.reload(&mut self.tex);
         }
+        self.reload_shaders(context)
     }
 
-    pub fn render(&mut self, context: &mut GL33Surface) -> Result<(), ()> {
+    /// The error from the last shader reload that failed, see [Renderer::reload].
+    pub fn shader_error(&self) -> Option<&str> {
+        self.shader_error.as_deref()
+    }
 
-        let back_buffer = context.back_buffer().unwrap();
-        let view = self.camera.matrix();
-
-        let triangles: Vec<_> = self.instances.iter().map(|i| {
-            context
-                .new_tess()
-                .set_vertices(&RECT[..])
-                .set_instances(&i[..])
-                .set_mode(Mode::Triangle)
-                .build()
-                .unwrap()
-        }).collect();
-
-        let particles: Vec<_> = self.particles
-            .iter()
-            .map(|s| {
-                (
-                    s.time,
-                    context
-                    .new_tess()
-                    .set_vertices(&RECT[..])
-                    .set_instances(&s.particles[..])
-                    .set_mode(Mode::Triangle)
-                    .build()
-                    .unwrap(),
+    #[cfg(debug_assertions)]
+    fn reload_shaders(&mut self, context: &mut GL33Surface) -> Result<(), String> {
+        let shaders = match &mut self.shader_files {
+            Some(shaders) => shaders,
+            None => return Ok(()),
+        };
+        let mut changed = false;
+        for (file, source) in shaders.files.iter_mut().zip(shaders.sources.iter_mut()) {
+            if let Some(bytes) = file.reload() {
+                *source = String::from_utf8_lossy(&bytes).into_owned();
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+
+        let [vs, fs, vs_particle] = &shaders.sources;
+        let sprite = build_program(context, vs, fs)
+            .map_err(|err| format!("failed to reload the sprite shader: {}", err));
+        let particle = build_program(context, vs_particle, fs)
+            .map_err(|err| format!("failed to reload the particle shader: {}", err));
+        self.shader_error = None;
+        match particle {
+            Ok(program) => self.particle_program = program,
+            Err(err) => self.shader_error = Some(err),
+        }
+        match sprite {
+            Ok(program) => self.sprite_program = program,
+            Err(err) => self.shader_error = Some(err),
+        }
+        match &self.shader_error {
+            Some(err) => Err(err.clone()),
+            None => Ok(()),
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn reload_shaders(&mut self, _context: &mut GL33Surface) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Snaps everything, including the camera, to a grid of `units_per_pixel`
+    /// world units. Stops pixel art from shimmering when things move by
+    /// fractions of a pixel.
+    pub fn pixel_perfect(&mut self, enabled: bool, units_per_pixel: f32) {
+        self.pixel_snap = if enabled { units_per_pixel } else { 0.0 };
+    }
+
+    /// Keeps the aspect ratio of `width` by `height` no matter the size of the
+    /// window, by filling the rest of the window with black bars.
+    /// Text and the debug overlay still cover the whole window.
+    pub fn set_logical_size(&mut self, width: u32, height: u32) {
+        assert!(width > 0 && height > 0);
+        self.logical_size = Some((width, height));
+    }
+
+    /// Stretches the scene over the whole window again.
+    pub fn clear_logical_size(&mut self) {
+        self.logical_size = None;
+    }
+
+    pub fn logical_size(&self) -> Option<(u32, u32)> {
+        self.logical_size
+    }
+
+    /// The part of a window of the given size that the scene is drawn to, as
+    /// `[x, y, width, height]` in fractions of the window, starting from the
+    /// bottom left. The whole window unless there's a [logical size](Renderer::set_logical_size).
+    pub fn letterbox(&self, (w, h): (u32, u32)) -> [f32; 4] {
+        let (lw, lh) = match self.logical_size {
+            Some(size) if w > 0 && h > 0 => size,
+            _ => return [0.0, 0.0, 1.0, 1.0],
+        };
+        let window_aspect = w as f32 / h as f32;
+        let logical_aspect = lw as f32 / lh as f32;
+        if window_aspect > logical_aspect {
+            // Bars on the sides.
+            let width = logical_aspect / window_aspect;
+            [(1.0 - width) / 2.0, 0.0, width, 1.0]
+        } else {
+            // Bars on the top and bottom.
+            let height = window_aspect / logical_aspect;
+            [0.0, (1.0 - height) / 2.0, 1.0, height]
+        }
+    }
+
+    /// Treats sprite sheets and colors as sRGB and blends in linear space,
+    /// converting back to sRGB when writing to the window. Without it
+    /// blending is done directly on the sRGB values, which makes soft edges
+    /// and additive particles too dark.
+    ///
+    /// Only affects [Renderer::render], render targets stay in sRGB space.
+    /// The window has to be created with
+    /// [GameBuilder::linear_color](crate::GameBuilder::linear_color).
+    pub fn set_linear_color(&mut self, enabled: bool) {
+        self.linear_color = enabled;
+    }
+
+    pub fn linear_color(&self) -> bool {
+        self.linear_color
+    }
+
+    /// Creates a new [RenderTarget] of the given size, and a sprite sheet
+    /// that will hold what's rendered to it.
+    pub fn add_render_target(
+        &mut self,
+        context: &mut GL33Surface,
+        size: (Pixels, Pixels),
+        tile_size: (Pixels, Pixels),
+    ) -> RenderTarget {
+        let framebuffer = context
+            .new_framebuffer::<Dim2, NormRGBA8UI, Depth32F>(
+                [size.0 as u32, size.1 as u32],
+                0,
+                Sampler::default(),
+            )
+            .expect("failed to create render target");
+        let image = Image::from_pixels(size.0, size.1, vec![0; size.0 * size.1 * 4]);
+        RenderTarget {
+            camera: Camera::new(),
+            framebuffer,
+            sheet: self.add_sprite_sheet(image, tile_size),
+        }
+    }
+
+    /// Renders everything that has been pushed so far to the target, as seen
+    /// from the target's camera. Nothing is removed, so the same things can be
+    /// rendered to the screen afterwards.
+    ///
+    /// The result is copied to the target's sprite sheet, which means it's
+    /// read back from the GPU. Don't do it too often.
+    pub fn render_to(&mut self, context: &mut GL33Surface, target: &mut RenderTarget) -> Result<(), ()> {
+        self.draw_to_framebuffer(context, &target.framebuffer, &target.camera)?;
+
+        let texels = target.framebuffer.color_slot().get_raw_texels().map_err(|_| ())?;
+        let sheet = &mut self.sprite_sheets[target.sheet];
+        sheet.image.texture_data = texels;
+        sheet.upload(&mut self.tex);
+        Ok(())
+    }
+
+    /// Renders everything pushed so far, as seen by [Renderer::camera], to a
+    /// new image. Like [Renderer::render_to] nothing is cleared, and text and
+    /// the overlay aren't drawn.
+    ///
+    /// Works with a hidden window, see [Game::new_headless](crate::Game::new_headless),
+    /// so the output can be checked in tests.
+    pub fn render_to_image(&mut self, context: &mut GL33Surface, size: (Pixels, Pixels)) -> Result<Image, ()> {
+        let framebuffer = context
+            .new_framebuffer::<Dim2, NormRGBA8UI, Depth32F>(
+                [size.0 as u32, size.1 as u32],
+                0,
+                Sampler::default(),
+            )
+            .map_err(|_| ())?;
+        let camera = self.camera;
+        self.draw_to_framebuffer(context, &framebuffer, &camera)?;
+
+        let texels = framebuffer.color_slot().get_raw_texels().map_err(|_| ())?;
+        Ok(Image::from_pixels(size.0, size.1, texels))
+    }
+
+    /// Draws the batches to a framebuffer, without clearing them.
+    fn draw_to_framebuffer(
+        &mut self,
+        context: &mut GL33Surface,
+        framebuffer: &Framebuffer<GLVer, Dim2, NormRGBA8UI, Depth32F>,
+        camera: &Camera,
+    ) -> Result<(), ()> {
+        self.upload_batches(context);
+
+        let pixel_snap = self.pixel_snap;
+        let Self {
+            tex,
+            batches,
+            parallax,
+            sprite_program,
+            particle_program,
+            sprite_tess,
+            particle_tess,
+            trail_tess,
+            ..
+        } = self;
+
+        let render = context
+            .new_pipeline_gate()
+            .pipeline(
+                framebuffer,
+                &PipelineState::default().set_clear_color([0.0, 0.0, 0.0, 0.0]),
+                |pipeline, mut shd_gate| {
+                    draw_scene(
+                        &pipeline,
+                        &mut shd_gate,
+                        tex,
+                        (&mut *sprite_program, &mut *particle_program),
+                        (&*sprite_tess, &*particle_tess, &*trail_tess),
+                        batches,
+                        (camera, &*parallax, pixel_snap, false),
+                    )
+                },
                 )
-            })
-        .collect();
+                    .assume();
+        if render.is_ok() {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Returns the number of tesses that had to be built.
+    fn upload_batches(&mut self, context: &mut GL33Surface) -> usize {
+        // Stable, so the push order is kept within a layer.
+        self.batches.sort_by_key(|batch| batch.key.layer);
+        let mut built = 0;
+        for (i, batch) in self.batches.iter().enumerate() {
+            let new = match &batch.contents {
+                BatchContents::Instances(instances) => {
+                    self.sprite_tess.upload(context, i, instances)
+                }
+                BatchContents::Particles(system) => {
+                    self.particle_tess.upload(context, i, &system.particles)
+                }
+                BatchContents::Trail(vertices) => {
+                    self.trail_tess.upload(context, i, vertices)
+                }
+            };
+            built += new as usize;
+        }
+        built
+    }
+
+    /// Counts from the last call to [Renderer::render].
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Draws everything that has been pushed to the window, and clears it
+    /// for the next frame. It's cleared even if something fails.
+    pub fn render(&mut self, context: &mut GL33Surface) -> Result<(), ()> {
+        let res = self.render_queued(context);
+        self.clear();
+        res
+    }
+
+    fn render_queued(&mut self, context: &mut GL33Surface) -> Result<(), ()> {
+        let back_buffer = context.back_buffer().map_err(|_| ())?;
+
+        let size = context.window().drawable_size();
+        let letterbox = self.letterbox(size);
+        let views: Vec<_> = if self.viewports.is_empty() {
+            let viewport = if self.logical_size.is_some() {
+                gl_viewport(letterbox, size)
+            } else {
+                GLViewport::Whole
+            };
+            vec![(viewport, self.camera)]
+        } else {
+            self.viewports
+                .iter()
+                .map(|v| (v.gl_viewport(size, letterbox), v.camera))
+                .collect()
+        };
+        let pixel_snap = self.pixel_snap;
+        let linear_color = self.linear_color;
+
+        let mut stats = RenderStats {
+            tess_built: self.upload_batches(context),
+            ..RenderStats::default()
+        };
+        stats.tess_built += self.overlay_tess.upload(context, 0, &self.overlay) as usize;
+        let screen_tint = self.screen_tint;
+        if screen_tint[3] > 0.0 {
+            let [r, g, b, a] = screen_tint;
+            let mut tint = Rect::new();
+            tint.scale(2.0, 2.0).rgba(r, g, b, a);
+            stats.tess_built += self.overlay_tess.upload(context, 1, &[tint.stamp()]) as usize;
+            stats.draw_calls += 1;
+        }
+        for batch in self.batches.iter() {
+            let count = match &batch.contents {
+                BatchContents::Instances(instances) => {
+                    stats.total_instances += instances.len();
+                    instances.len()
+                }
+                BatchContents::Particles(system) => {
+                    stats.total_particles += system.particles.len();
+                    system.particles.len()
+                }
+                BatchContents::Trail(vertices) => vertices.len(),
+            };
+            if count != 0 {
+                stats.draw_calls += views.len();
+            }
+        }
+        if !self.overlay.is_empty() {
+            stats.draw_calls += 1;
+            stats.total_instances += self.overlay.len();
+        }
+        self.stats = stats;
 
         self.font.process_queued(context);
 
+        let Self {
+            tex,
+            batches,
+            parallax,
+            overlay,
+            font,
+            sprite_program,
+            particle_program,
+            sprite_tess,
+            particle_tess,
+            trail_tess,
+            overlay_tess,
+            ..
+        } = self;
+
+        let mut ok = true;
+        for (v, (viewport, camera)) in views.iter().enumerate() {
+            // Only the first pass clears the screen.
+            let state = PipelineState::default()
+                .set_viewport(*viewport)
+                .enable_clear_color(v == 0)
+                .enable_srgb(linear_color);
+
+            let render = context
+                .new_pipeline_gate()
+                .pipeline(
+                    &back_buffer,
+                    &state,
+                    |pipeline, mut shd_gate| {
+                        draw_scene(
+                            &pipeline,
+                            &mut shd_gate,
+                            tex,
+                            (&mut *sprite_program, &mut *particle_program),
+                            (&*sprite_tess, &*particle_tess, &*trail_tess),
+                            batches,
+                            (camera, &*parallax, pixel_snap, linear_color),
+                        )
+                    },
+                    )
+                        .assume();
+            ok &= render.is_ok();
+        }
+
+        // Text and the overlay are drawn once, over the whole window.
         let render = context
             .new_pipeline_gate()
             .pipeline(
                 &back_buffer,
-                &PipelineState::default(),
+                &PipelineState::default()
+                    .set_viewport(GLViewport::Whole)
+                    .enable_clear_color(false),
                 |mut pipeline, mut shd_gate| {
-                    let bound_tex = pipeline.bind_texture(&mut self.tex)?;
-
-                    let state = RenderState::default().set_depth_test(None).set_blending(Blending {
-                        equation: Equation::Additive,
-                        src: Factor::SrcAlpha,
-                        dst: Factor::SrcAlphaComplement,
-                    });
-
-                    for i in 0..triangles.len().max(particles.len()) {
-                        if let Some(triangle) = triangles.get(i) {
-                            shd_gate.shade(&mut self.sprite_program, |mut iface, uni, mut rdr_gate| {
-                                iface.set(&uni.tex, bound_tex.binding());
-                                iface.set(&uni.view, view.into());
-                                rdr_gate.render(&state, |mut tess_gate| tess_gate.render(triangle))
-                            })?;
-                        }
-
-                        if let Some((t, p)) = particles.get(i) {
-                            shd_gate.shade(&mut self.particle_program, |mut iface, uni, mut rdr_gate| {
-                                iface.set(&uni.tex, bound_tex.binding());
-                                iface.set(&uni.view, view.into());
-                                rdr_gate.render(&state, |mut tess_gate| {
-                                    iface.set(&uni.t, *t);
-                                    tess_gate.render(p)?;
-                                    Ok(())
-                                })
-                            })?;
-                        }
+                    let bound_tex = pipeline.bind_texture(tex)?;
+
+                    // The screen tint is in the second tess, and goes under the overlay.
+                    let tint = if screen_tint[3] > 0.0 { 1 } else { 0 };
+                    for &(slot, count) in [(1, tint), (0, overlay.len())].iter() {
+                        let triangles = match overlay_tess.get(slot) {
+                            Some(triangles) if count > 0 => triangles,
+                            _ => continue,
+                        };
+                        let triangles = triangles.inst_view(.., count).unwrap();
+                        let state = BlendMode::Alpha.render_state();
+                        shd_gate.shade(sprite_program, |mut iface, uni, mut rdr_gate| {
+                            iface.set(&uni.tex, bound_tex.binding());
+                            iface.set(&uni.view, Matrix4::<f32>::identity().into());
+                            iface.set(&uni.pixel_snap, 0.0);
+                            iface.set(&uni.linear_color, 0);
+                            rdr_gate.render(&state, |mut tess_gate| tess_gate.render(triangles))
+                        })?;
                     }
 
-                    self.font
-                        .draw_queued(&mut pipeline, &mut shd_gate, 1024, 720)
+                    font
+                        .draw_queued(&mut pipeline, &mut shd_gate, size.0, size.1)
                         .expect("failed to render glyphs");
 
                     Ok(())
                 },
                 )
                     .assume();
+        ok &= render.is_ok();
 
-        let res = if render.is_ok() {
+        if ok {
             context.window().gl_swap_window();
             Ok(())
         } else {
             Err(())
-        };
+        }
+    }
+}
 
-        self.instances = vec![Vec::new()];
-        self.particles.clear();
-        res
+/// Draws all the batches, in order, as seen through the camera.
+fn draw_scene(
+    pipeline: &Pipeline<GLVer>,
+    shd_gate: &mut ShadingGate<GLVer>,
+    tex: &mut Tex,
+    (sprite_program, particle_program): (&mut ShaderProgram, &mut ShaderProgram),
+    (sprite_tess, particle_tess, trail_tess): (&InstanceTessPool, &ParticleTessPool, &TrailTessPool),
+    batches: &[Batch],
+    (camera, parallax, pixel_snap, linear_color): (&Camera, &HashMap<i32, f32>, f32, bool),
+) -> Result<(), PipelineError> {
+    let bound_tex = pipeline.bind_texture(tex)?;
+
+    for (i, batch) in batches.iter().enumerate() {
+        let state = batch.key.blend_mode.render_state();
+        let factor = parallax.get(&batch.key.layer).copied().unwrap_or(1.0);
+        let view = camera.with_parallax(factor).snapped(pixel_snap).matrix();
+
+        match &batch.contents {
+            BatchContents::Instances(instances) => {
+                let triangles = match sprite_tess.get(i) {
+                    Some(triangles) if !instances.is_empty() => triangles,
+                    _ => continue,
+                };
+                let triangles = triangles.inst_view(.., instances.len()).unwrap();
+                let state = if instances.iter().any(|instance| *instance.depth != 0.0) {
+                    state.set_depth_test(Some(DepthComparison::LessOrEqual))
+                } else {
+                    state
+                };
+                shd_gate.shade(sprite_program, |mut iface, uni, mut rdr_gate| {
+                    iface.set(&uni.tex, bound_tex.binding());
+                    iface.set(&uni.view, view.into());
+                    iface.set(&uni.pixel_snap, pixel_snap);
+                    iface.set(&uni.linear_color, linear_color as i32);
+                    rdr_gate.render(&state, |mut tess_gate| tess_gate.render(triangles))
+                })?;
+            }
+
+            BatchContents::Particles(system) => {
+                let p = match particle_tess.get(i) {
+                    Some(p) if !system.particles.is_empty() => p,
+                    _ => continue,
+                };
+                let p = p.inst_view(.., system.particles.len()).unwrap();
+                shd_gate.shade(particle_program, |mut iface, uni, mut rdr_gate| {
+                    iface.set(&uni.tex, bound_tex.binding());
+                    iface.set(&uni.view, view.into());
+                    iface.set(&uni.pixel_snap, pixel_snap);
+                    iface.set(&uni.linear_color, linear_color as i32);
+                    rdr_gate.render(&state, |mut tess_gate| {
+                        iface.set(&uni.t, system.time);
+                        iface.set(&uni.tint, system.tint);
+                        iface.set(&uni.easing, system.easing as i32);
+                        tess_gate.render(p)?;
+                        Ok(())
+                    })
+                })?;
+            }
+
+            BatchContents::Trail(vertices) => {
+                let strip = match trail_tess.get(i) {
+                    Some(strip) if !vertices.is_empty() => strip,
+                    _ => continue,
+                };
+                let strip = strip.view(..vertices.len()).unwrap();
+                shd_gate.shade(sprite_program, |mut iface, uni, mut rdr_gate| {
+                    iface.set(&uni.tex, bound_tex.binding());
+                    iface.set(&uni.view, view.into());
+                    iface.set(&uni.pixel_snap, pixel_snap);
+                    iface.set(&uni.linear_color, linear_color as i32);
+                    rdr_gate.render(&state, |mut tess_gate| tess_gate.render(strip))
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn hsv_primaries() {
+        assert_close(hsv_to_rgb([0.0, 1.0, 1.0]), [1.0, 0.0, 0.0]);
+        assert_close(hsv_to_rgb([120.0, 1.0, 1.0]), [0.0, 1.0, 0.0]);
+        assert_close(hsv_to_rgb([240.0, 1.0, 1.0]), [0.0, 0.0, 1.0]);
+        assert_close(hsv_to_rgb([60.0, 1.0, 0.5]), [0.5, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn hue_wraps_around() {
+        assert_close(hsv_to_rgb([360.0, 1.0, 1.0]), hsv_to_rgb([0.0, 1.0, 1.0]));
+        assert_close(hsv_to_rgb([-120.0, 1.0, 1.0]), hsv_to_rgb([240.0, 1.0, 1.0]));
+        assert_close(hsv_to_rgb([480.0, 1.0, 1.0]), hsv_to_rgb([120.0, 1.0, 1.0]));
+        // Just below a full turn is still red.
+        assert_close(hsv_to_rgb([-1e-7, 1.0, 1.0]), [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn grays_have_no_hue_or_saturation() {
+        assert_close(rgb_to_hsv([0.5, 0.5, 0.5]), [0.0, 0.0, 0.5]);
+        assert_close(rgb_to_hsv([0.0, 0.0, 0.0]), [0.0, 0.0, 0.0]);
+        assert_close(hsv_to_rgb([200.0, 0.0, 0.25]), [0.25, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn rgb_to_hsv_round_trips() {
+        for &rgb in [
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 0.5],
+            [0.25, 0.75, 0.5],
+            [0.1, 0.2, 0.9],
+            [0.9, 0.8, 0.1],
+        ]
+        .iter()
+        {
+            let [h, s, v] = rgb_to_hsv(rgb);
+            assert!((0.0..360.0).contains(&h), "{:?} has hue {}", rgb, h);
+            assert!((0.0..=1.0).contains(&s));
+            assert!((0.0..=1.0).contains(&v));
+            assert_close(hsv_to_rgb([h, s, v]), rgb);
+        }
+    }
+
+    fn sheet(half_texel_inset: bool) -> SpriteSheet {
+        SpriteSheet {
+            id: 2,
+            image: Image::solid(64, 32, [0; 4]),
+            tile_size: (16, 8),
+            sheet_size: [512, 512, 4],
+            half_texel_inset,
+        }
+    }
+
+    #[test]
+    fn region_to_pixels_undoes_grid() {
+        for &inset in [false, true].iter() {
+            let sheet = sheet(inset);
+            assert_eq!(sheet.region_to_pixels(sheet.grid(0, 0)), (0, 0, 16, 8));
+            assert_eq!(sheet.region_to_pixels(sheet.grid(3, 2)), (48, 16, 16, 8));
+        }
+    }
+
+    #[test]
+    fn region_to_pixels_of_an_empty_region() {
+        let sheet = sheet(false);
+        let (layer, _) = sheet.grid(0, 0);
+        assert_eq!(sheet.region_to_pixels((layer, [0.25, 0.25, 0.25, 0.25])), (128, 128, 0, 0));
     }
 }
@@ -0,0 +1,71 @@
+use super::{SpriteSheet, SpriteSheetID, Sprite, Tint, Transform};
+
+use std::collections::HashMap;
+
+/// A font made from a sprite sheet, with one glyph per tile.
+/// Great for pixel art, where every glyph should look exactly as drawn.
+///
+/// Text is drawn one [Sprite] per character, see [Renderer::push_bitmap_text](super::Renderer::push_bitmap_text).
+#[derive(Clone, Debug)]
+pub struct BitmapFont {
+    pub sheet: SpriteSheetID,
+    /// The size of a character in world units.
+    pub char_size: [f32; 2],
+    /// Extra space between characters and lines, in world units.
+    pub spacing: [f32; 2],
+    pub color: [f32; 4],
+
+    /// Which tile each character is drawn with.
+    tiles: HashMap<char, (usize, usize)>,
+}
+
+impl BitmapFont {
+    /// The characters in `chars` are placed in the sheet in reading order,
+    /// `columns` characters per row, starting from the top left.
+    pub fn new(sheet: SpriteSheetID, columns: usize, chars: &str) -> Self {
+        let tiles = chars
+            .chars()
+            .enumerate()
+            .map(|(i, c)| (c, (i % columns, i / columns)))
+            .collect();
+        Self {
+            sheet,
+            char_size: [1.0, 1.0],
+            spacing: [0.0, 0.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+
+            tiles,
+        }
+    }
+
+    /// Lays out the text with the top left corner at `(x, y)`.
+    /// Newlines start a new line, and characters that aren't in the font
+    /// are left as blank space.
+    pub fn sprites(&self, sheet: &SpriteSheet, text: &str, x: f32, y: f32) -> Vec<Sprite> {
+        let [w, h] = self.char_size;
+        let advance = [w + self.spacing[0], h + self.spacing[1]];
+
+        let mut sprites = Vec::new();
+        let (mut column, mut row) = (0, 0);
+        for c in text.chars() {
+            if c == '\n' {
+                column = 0;
+                row += 1;
+                continue;
+            }
+            if let Some(&(tx, ty)) = self.tiles.get(&c) {
+                let mut sprite = Sprite::new(sheet.grid(tx, ty));
+                sprite
+                    .scale(w, h)
+                    .at(
+                        x + column as f32 * advance[0] + w / 2.0,
+                        y - row as f32 * advance[1] - h / 2.0,
+                    )
+                    .rgba(self.color[0], self.color[1], self.color[2], self.color[3]);
+                sprites.push(sprite);
+            }
+            column += 1;
+        }
+        sprites
+    }
+}
@@ -1,9 +1,11 @@
-use super::{prelude::*, SpriteRegion};
+use super::{prelude::*, SpriteRegion, SpriteSheet, Transform};
+
+use cgmath::Vector2;
 
 use std::f32::consts::PI;
 use sungod::Ra;
 
-use crate::random::{RandomProperty, Uniform};
+use crate::random::{Distribute, Easing, RandomProperty, Uniform};
 
 /// Creates a particle system.
 ///
@@ -38,31 +40,158 @@ macro_rules! particle_system {
     };
 }
 
+/// Where particles spawn, relative to the particle system's position.
+#[derive(Copy, Clone, Debug)]
+pub enum SpawnShape {
+    /// Uses the `x` and `y` properties of the particle system.
+    Rect,
+    /// Anywhere inside a circle.
+    Circle { radius: f32 },
+    /// Anywhere between two circles.
+    Ring { inner: f32, outer: f32 },
+    /// Anywhere on a line from the position to `to`.
+    Line { to: [f32; 2] },
+}
+
+impl Default for SpawnShape {
+    fn default() -> Self {
+        SpawnShape::Rect
+    }
+}
+
+impl SpawnShape {
+    /// Picks a random point in the shape.
+    fn sample(&self, x: &RandomProperty, y: &RandomProperty) -> [f32; 2] {
+        let angle = || Uniform.between(0.0, 2.0 * PI);
+        match *self {
+            SpawnShape::Rect => [x.sample(), y.sample()],
+            SpawnShape::Circle { radius } => {
+                // The square root spreads the points evenly over the area.
+                let r = radius * Uniform.sample().sqrt();
+                let a = angle();
+                [a.cos() * r, a.sin() * r]
+            }
+            SpawnShape::Ring { inner, outer } => {
+                let r = Uniform.between(inner * inner, outer * outer).sqrt();
+                let a = angle();
+                [a.cos() * r, a.sin() * r]
+            }
+            SpawnShape::Line { to } => {
+                let t = Uniform.sample();
+                [to[0] * t, to[1] * t]
+            }
+        }
+    }
+}
+
+/// Where particles are moved, see [ParticleSystem::simulation].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Simulation {
+    /// In the shader. Almost free, but particles can only move as the
+    /// properties say.
+    Gpu,
+    /// In [ParticleSystem::update]. Slower, but particles can be pushed
+    /// around by [ParticleSystem::custom_force].
+    Cpu,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::Gpu
+    }
+}
+
+/// Changes the position and velocity of a particle, given the delta-time.
+/// See [ParticleSystem::custom_force].
+pub type CustomForce = Box<dyn FnMut(&mut [f32; 2], &mut [f32; 2], f32)>;
+
+/// Moves one axis of a particle `dt` seconds forward. The same closed form
+/// as `travel` in the shader, so the path doesn't depend on the delta.
+fn travel(p: f32, v: f32, a: f32, k: f32, dt: f32) -> (f32, f32) {
+    if k.abs() < 1e-5 {
+        return (p + v * dt + 0.5 * a * dt * dt, v + a * dt);
+    }
+    let terminal = a / k;
+    let decay = (-k * dt).exp();
+    (
+        p + terminal * dt + (v - terminal) * (1.0 - decay) / k,
+        terminal + (v - terminal) * decay,
+    )
+}
+
 /// An actual particle system. Contains a lot of knobs.
 ///
 /// Particles are rendered only on the GPU and as such are _almost_ free.
-#[derive(Default)]
+///
+/// Where a particle is only depends on how old it is, so particles move the
+/// same no matter the frame rate. How many particles are spawned is up to
+/// the caller though. With [Simulation::Cpu] the particles are moved a step
+/// at a time instead. Drag and acceleration still follow the same path, but
+/// attractors and custom forces depend a little on the frame rate.
 pub struct ParticleSystem {
     pub time: f32,
     pub particles: Vec<Particle>,
 
-    // TODO(ed): GRR!! I want this to be Vector2
-    // and implement Transform
-    pub position: [f32; 2],
+    pub position: Vector2<f32>,
+    /// Scales the spawn shape.
+    pub scale: Vector2<f32>,
+    /// Rotates the spawn shape and the velocity of new particles.
+    pub rotation: f32,
 
     pub sprites: Vec<SpriteRegion>,
+    /// If true, `sprites` is treated as an animation that is played once over
+    /// the lifetime of each particle, otherwise a random sprite is picked on spawn.
+    ///
+    /// The frames have to be evenly spaced along the x-axis of the same
+    /// sprite sheet, like the ones given by [SpriteSheet::grid] with
+    /// increasing `tx`.
+    ///
+    /// [SpriteSheet::grid]: super::SpriteSheet::grid
+    pub animate_sprites: bool,
+
+    /// How the particles move from their start to their end size and color
+    /// over their lifetime.
+    pub easing: Easing,
+
+    /// Moving particles on the CPU allows for things the shader can't do,
+    /// like colliding with the ground.
+    pub simulation: Simulation,
+    /// Called for every particle on every update with [Simulation::Cpu],
+    /// after it has been moved.
+    pub custom_force: Option<CustomForce>,
+    /// Points that pull particles towards them, with a strength that falls
+    /// off with the distance. Negative strengths push them away instead.
+    /// Only used with [Simulation::Cpu].
+    pub attractors: Vec<(Vector2<f32>, f32)>,
+
+    /// A color multiplied onto every particle in the system.
+    /// Composes with the per-particle start and end colors. White by default.
+    pub tint: [f32; 4],
+
+    /// Where particles spawn.
+    pub spawn_shape: SpawnShape,
+    /// If true, `vel_angle` is relative to the direction from 'position'
+    /// to where the particle spawned. Gives nice radial bursts.
+    pub radial_velocity: bool,
 
     /// Allowed x-coordinates to spawn on, relative to 'position'.
+    /// Only used with [SpawnShape::Rect].
     pub x: RandomProperty,
     /// Allowed y-coordinates to spawn on, relative to 'position'.
+    /// Only used with [SpawnShape::Rect].
     pub y: RandomProperty,
 
     /// How long, in seconds, the particle should live.
     pub lifetime: RandomProperty,
 
-    // TODO(ed): Options for how this is selected
     /// The angle of the velocity in radians.
+    /// Not used if `direction` is set.
     pub vel_angle: RandomProperty,
+    /// If set, particles are emitted in a cone aimed in this direction,
+    /// in radians, instead of using `vel_angle`.
+    pub direction: Option<f32>,
+    /// How wide the cone around `direction` is, in radians.
+    pub spread: f32,
     /// How fast a particle should move when it spawns.
     pub vel_magnitude: RandomProperty,
 
@@ -112,6 +241,54 @@ pub struct ParticleSystem {
     pub end_alpha: RandomProperty,
 }
 
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self {
+            time: 0.0,
+            particles: Vec::new(),
+            position: Vector2::new(0.0, 0.0),
+            scale: Vector2::new(1.0, 1.0),
+            rotation: 0.0,
+            sprites: Vec::new(),
+            animate_sprites: false,
+            easing: Easing::default(),
+            simulation: Simulation::default(),
+            custom_force: None,
+            attractors: Vec::new(),
+            tint: [1.0, 1.0, 1.0, 1.0],
+            spawn_shape: SpawnShape::default(),
+            radial_velocity: false,
+            x: RandomProperty::default(),
+            y: RandomProperty::default(),
+            lifetime: RandomProperty::default(),
+            vel_angle: RandomProperty::default(),
+            direction: None,
+            spread: 0.0,
+            vel_magnitude: RandomProperty::default(),
+            acc_angle: RandomProperty::default(),
+            acc_magnitude: RandomProperty::default(),
+            drag: RandomProperty::default(),
+            angle: RandomProperty::default(),
+            angle_velocity: RandomProperty::default(),
+            angle_drag: RandomProperty::default(),
+            start_sx: RandomProperty::default(),
+            start_sy: RandomProperty::default(),
+            end_sx: RandomProperty::default(),
+            end_sy: RandomProperty::default(),
+            start_red: RandomProperty::default(),
+            start_green: RandomProperty::default(),
+            start_blue: RandomProperty::default(),
+            start_alpha: RandomProperty::default(),
+            end_red: RandomProperty::default(),
+            end_green: RandomProperty::default(),
+            end_blue: RandomProperty::default(),
+            end_alpha: RandomProperty::default(),
+        }
+    }
+}
+
+impl_transform_for!(ParticleSystem);
+
 impl ParticleSystem {
     pub fn new() -> Self {
         Self {
@@ -131,6 +308,12 @@ impl ParticleSystem {
         }
     }
 
+    /// Replaces [ParticleSystem::sprites] with the tiles of the sheet, in order.
+    /// See [SpriteSheet::grid].
+    pub fn set_sheet_grid(&mut self, sheet: &SpriteSheet, frames: &[(usize, usize)]) {
+        self.sprites = frames.iter().map(|&(tx, ty)| sheet.grid(tx, ty)).collect();
+    }
+
     /// Steps the particle system some delta-time forward. Removes dead particles.
     pub fn update(&mut self, delta: f32) {
         self.time += delta;
@@ -139,11 +322,65 @@ impl ParticleSystem {
             .into_iter()
             .filter(|x| *x.lifetime > (self.time - *x.spawn))
             .collect();
+
+        if self.simulation == Simulation::Cpu {
+            self.simulate(delta);
+        }
+    }
+
+    /// Moves the particles a step forward, like the shader does all at once.
+    fn simulate(&mut self, delta: f32) {
+        // Stops particles from being flung away when they get too close.
+        const MIN_ATTRACTOR_DISTANCE: f32 = 0.01;
+
+        for particle in self.particles.iter_mut() {
+            let mut position = *particle.position;
+            let mut velocity = *particle.velocity;
+            let mut acceleration = *particle.acceleration;
+            let drag = *particle.drag;
+
+            for (point, strength) in self.attractors.iter() {
+                let (dx, dy) = (point.x - position[0], point.y - position[1]);
+                let distance = (dx * dx + dy * dy).sqrt().max(MIN_ATTRACTOR_DISTANCE);
+                // The direction, divided by the distance.
+                let pull = strength / (distance * distance);
+                acceleration[0] += dx * pull;
+                acceleration[1] += dy * pull;
+            }
+
+            let (x, vx) = travel(position[0], velocity[0], acceleration[0], drag, delta);
+            let (y, vy) = travel(position[1], velocity[1], acceleration[1], drag, delta);
+            position = [x, y];
+            velocity = [vx, vy];
+            if let Some(force) = &mut self.custom_force {
+                force(&mut position, &mut velocity, delta);
+            }
+
+            particle.position = IPosition::new(position);
+            particle.velocity = PVelocity::new(velocity);
+        }
     }
 
     /// Spawns a new particle.
     pub fn spawn(&mut self) {
-        let vel_angle = self.vel_angle.sample();
+        let offset = self.spawn_shape.sample(&self.x, &self.y);
+        let offset = [offset[0] * self.scale.x, offset[1] * self.scale.y];
+        let (sin, cos) = self.rotation.sin_cos();
+        let offset = [
+            offset[0] * cos - offset[1] * sin,
+            offset[0] * sin + offset[1] * cos,
+        ];
+
+        let vel_angle = match self.direction {
+            Some(direction) => direction + Uniform.between(-self.spread / 2.0, self.spread / 2.0),
+            None => self.vel_angle.sample(),
+        };
+        let vel_angle = if self.radial_velocity {
+            // The offset is already rotated.
+            offset[1].atan2(offset[0]) + vel_angle
+        } else {
+            vel_angle + self.rotation
+        };
         let vel_magnitude = self.vel_magnitude.sample();
 
         let acc_angle = self.acc_angle.sample();
@@ -151,19 +388,30 @@ impl ParticleSystem {
 
         let (sheet, uv) = if self.sprites.is_empty() {
             &(-1.0, [0.0, 0.0, 0.0, 0.0])
+        } else if self.animate_sprites {
+            &self.sprites[0]
         } else {
             let i = Ra::ggen::<usize>();
             let i = i % self.sprites.len();
             &self.sprites[i]
         };
 
+        let frames = if self.animate_sprites && self.sprites.len() > 1 {
+            [
+                self.sprites.len() as f32,
+                self.sprites[1].1[0] - self.sprites[0].1[0],
+            ]
+        } else {
+            [1.0, 0.0]
+        };
+
         self.particles.push(Particle {
             spawn: PSpawn::new(self.time),
             lifetime: PLifetime::new(self.lifetime.sample()),
 
             position: IPosition::new([
-                self.x.sample() + self.position[0],
-                self.y.sample() + self.position[1],
+                offset[0] + self.position.x,
+                offset[1] + self.position.y,
             ]),
             velocity: PVelocity::new([
                 vel_angle.cos() * vel_magnitude,
@@ -203,6 +451,7 @@ impl ParticleSystem {
 
             sheet: ISheet::new(*sheet),
             uv: IUV::new(*uv),
+            frames: PFrames::new(frames),
         });
     }
 
@@ -215,10 +464,22 @@ impl ParticleSystem {
     /// Copies out the rendering information.
     pub fn freeze(&self) -> FrozenParticles {
         // TODO(ed): Can we get rid of this clone?
+        let mut particles = self.particles.clone();
+        if self.simulation == Simulation::Cpu {
+            // The particles are already where they should be, so the shader
+            // shouldn't move them.
+            for particle in particles.iter_mut() {
+                particle.velocity = PVelocity::new([0.0, 0.0]);
+                particle.acceleration = PAcceleration::new([0.0, 0.0]);
+                particle.drag = PDrag::new(0.0);
+            }
+        }
         FrozenParticles {
-            position: self.position,
+            position: self.position.into(),
             time: self.time,
-            particles: self.particles.clone(),
+            tint: self.tint,
+            easing: self.easing,
+            particles,
         }
     }
 }
@@ -228,5 +489,7 @@ impl ParticleSystem {
 pub struct FrozenParticles {
     pub position: [f32; 2],
     pub time: f32,
+    pub tint: [f32; 4],
+    pub easing: Easing,
     pub particles: Vec<Particle>,
 }
@@ -23,6 +23,10 @@ pub enum VertexSemantics {
     ISheet,
     #[sem(name = "uv", repr = "[f32; 4]", wrapper = "IUV")]
     IUV,
+    #[sem(name = "shear", repr = "[f32; 2]", wrapper = "IShear")]
+    IShear,
+    #[sem(name = "depth", repr = "f32", wrapper = "IDepth")]
+    IDepth,
 
     #[sem(name = "spawn", repr = "f32", wrapper = "PSpawn")]
     PSpawn,
@@ -45,6 +49,9 @@ pub enum VertexSemantics {
     PStartColor,
     #[sem(name = "end_color", repr = "[f32; 4]", wrapper = "PEndColor")]
     PEndColor,
+
+    #[sem(name = "frames", repr = "[f32; 2]", wrapper = "PFrames")]
+    PFrames,
 }
 
 /// What is placed in a simple vertex. Only used for the simple Rect.
@@ -58,6 +65,11 @@ pub struct Vertex {
 
 /// All the ways to change how an instance is rendered.
 /// Used internally.
+///
+/// An instance is 17 floats, 68 bytes. Untextured rects set `sheet` to -1
+/// and their `uv` is ignored, so five of those floats are wasted for them. A leaner
+/// layout would need its own shader and a new draw call every time rects
+/// and sprites take turns, which costs more than the bytes it saves.
 #[repr(C)]
 #[derive(Vertex, Copy, Clone, PartialEq, Debug)]
 #[vertex(sem = "VertexSemantics", instanced = "true")]
@@ -68,6 +80,21 @@ pub struct Instance {
     pub color: IColor,
     pub sheet: ISheet,
     pub uv: IUV,
+    pub shear: IShear,
+    pub depth: IDepth,
+}
+
+/// A corner of a [Trail](super::Trail), drawn as a triangle strip with the
+/// sprite shader. The attributes that are left out are zero, which puts
+/// the corner right at `position`.
+/// Used internally.
+#[repr(C)]
+#[derive(Vertex, Copy, Clone, PartialEq, Debug)]
+#[vertex(sem = "VertexSemantics")]
+pub struct TrailVertex {
+    pub position: IPosition,
+    pub color: IColor,
+    pub sheet: ISheet,
 }
 
 /// What is needed to render a particle.
@@ -92,6 +119,8 @@ pub struct Particle {
 
     pub sheet: ISheet,
     pub uv: IUV,
+    /// The number of animation frames, and the distance between them in uv-space.
+    pub frames: PFrames,
 }
 
 /// Interface for passing uniforms.
@@ -100,6 +129,17 @@ pub struct Particle {
 pub struct ShaderInterface {
     #[uniform(unbound)]
     pub t: Uniform<f32>,
+    #[uniform(unbound)]
+    pub tint: Uniform<[f32; 4]>,
+    /// Which [Easing](crate::random::Easing) to use for particles.
+    #[uniform(unbound)]
+    pub easing: Uniform<i32>,
+    /// The size of a pixel in world units, or 0 to not snap.
+    #[uniform(unbound)]
+    pub pixel_snap: Uniform<f32>,
+    /// Non-zero to blend in linear space, see [Renderer::set_linear_color](super::Renderer::set_linear_color).
+    #[uniform(unbound)]
+    pub linear_color: Uniform<i32>,
 
     pub view: Uniform<[[f32; 4]; 4]>,
 
@@ -0,0 +1,182 @@
+use super::prelude::*;
+use super::Transform;
+
+use cgmath::Vector2;
+
+use std::collections::VecDeque;
+
+use crate::random::{Easing, RandomProperty};
+
+/// How far a mitered corner may reach, in half widths. Sharper corners are
+/// cut off here instead of turning into long spikes.
+const MITER_LIMIT: f32 = 4.0;
+
+/// A ribbon that follows a moving emitter, like the trail behind a
+/// projectile or a sword swipe.
+///
+/// Move the trail with [Transform] and call [Trail::update] every frame.
+/// The trail is drawn as one connected strip, with mitered corners, that
+/// shrinks and fades like a particle over its lifetime. Rotation doesn't
+/// change anything, the trail follows its own path.
+pub struct Trail {
+    pub time: f32,
+
+    /// Where the emitter is now.
+    pub position: Vector2<f32>,
+    /// The y-component scales the width, like the height of a
+    /// [Rect](super::Rect) lying along the trail.
+    pub scale: Vector2<f32>,
+    pub rotation: f32,
+
+    /// How long, in seconds, a point of the trail lives.
+    /// Sampled once for every point.
+    pub lifetime: RandomProperty,
+    /// How far the emitter has to move before a new point is spawned.
+    pub min_distance: f32,
+
+    /// How the points move from their start to their end width and color
+    /// over their lifetime.
+    pub easing: Easing,
+
+    /// How wide the trail is when a point spawns.
+    pub start_width: f32,
+    /// How wide the trail is when a point dies.
+    pub end_width: f32,
+
+    /// The color of the trail when a point spawns.
+    pub start_color: [f32; 4],
+    /// The color of the trail when a point dies.
+    pub end_color: [f32; 4],
+
+    /// Where the emitter has been, when, and for how long it's remembered.
+    /// Oldest first.
+    points: VecDeque<([f32; 2], f32, f32)>,
+}
+
+impl_transform_for!(Trail);
+
+impl Trail {
+    pub fn new() -> Self {
+        Self {
+            time: 0.0,
+
+            position: Vector2::new(0.0, 0.0),
+            scale: Vector2::new(1.0, 1.0),
+            rotation: 0.0,
+
+            lifetime: RandomProperty::constant(1.0),
+            min_distance: 0.01,
+
+            easing: Easing::default(),
+
+            start_width: 0.1,
+            end_width: 0.0,
+
+            start_color: [1.0, 1.0, 1.0, 1.0],
+            end_color: [1.0, 1.0, 1.0, 0.0],
+
+            points: VecDeque::new(),
+        }
+    }
+
+    /// Steps the trail some delta-time forward. Spawns a point at the
+    /// current position if the emitter has moved, and removes dead points.
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta;
+
+        let position = [self.position.x, self.position.y];
+        let moved = match self.points.back() {
+            Some((p, _, _)) => distance(*p, position) >= self.min_distance,
+            None => true,
+        };
+        if moved {
+            self.points.push_back((position, self.time, self.lifetime.sample()));
+        }
+
+        let time = self.time;
+        self.points.retain(|(_, spawn, lifetime)| time - spawn <= *lifetime);
+    }
+
+    /// Removes the whole trail.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    /// The width and color of a point that has lived for `lerp` of its lifetime.
+    fn at(&self, lerp: f32) -> (f32, [f32; 4]) {
+        let lerp = self.easing.apply(lerp);
+        let mix = |start: f32, end: f32| start + (end - start) * lerp;
+        let color = [
+            mix(self.start_color[0], self.end_color[0]),
+            mix(self.start_color[1], self.end_color[1]),
+            mix(self.start_color[2], self.end_color[2]),
+            mix(self.start_color[3], self.end_color[3]),
+        ];
+        (mix(self.start_width, self.end_width) * self.scale.y, color)
+    }
+
+    /// Two vertices for every point, one on each side, oldest first. The
+    /// newest point is the current position. Drawn as a triangle strip.
+    pub(crate) fn vertices(&self) -> Vec<TrailVertex> {
+        let head = std::iter::once(([self.position.x, self.position.y], self.time, 1.0));
+        let mut points: Vec<([f32; 2], f32, f32)> = Vec::with_capacity(self.points.len() + 1);
+        for point in self.points.iter().copied().chain(head) {
+            // Points on top of each other have no direction.
+            if points.last().map_or(true, |(p, _, _)| distance(*p, point.0) > 0.0) {
+                points.push(point);
+            }
+        }
+        if points.len() < 2 {
+            return Vec::new();
+        }
+
+        // The normal of every segment, pointing to the left.
+        let normals: Vec<[f32; 2]> = points
+            .windows(2)
+            .map(|w| {
+                let (a, b) = (w[0].0, w[1].0);
+                let length = distance(a, b);
+                [-(b[1] - a[1]) / length, (b[0] - a[0]) / length]
+            })
+            .collect();
+
+        let mut vertices = Vec::with_capacity(points.len() * 2);
+        for (i, (p, spawn, lifetime)) in points.iter().enumerate() {
+            let before = normals[i.saturating_sub(1)];
+            let after = normals[i.min(normals.len() - 1)];
+
+            // The corner is pushed out along the average of the normals, far
+            // enough for both segments to keep their width.
+            let miter = [before[0] + after[0], before[1] + after[1]];
+            let length = (miter[0] * miter[0] + miter[1] * miter[1]).sqrt();
+            let (miter, stretch) = if length < 1e-5 {
+                // The trail turns all the way around.
+                (after, 1.0)
+            } else {
+                let miter = [miter[0] / length, miter[1] / length];
+                let cos = miter[0] * after[0] + miter[1] * after[1];
+                (miter, (1.0 / cos).min(MITER_LIMIT))
+            };
+
+            let lerp = ((self.time - spawn) / lifetime).max(0.0).min(1.0);
+            let (width, color) = self.at(lerp);
+            let offset = width / 2.0 * stretch;
+            for side in [1.0, -1.0].iter() {
+                vertices.push(TrailVertex {
+                    position: IPosition::new([
+                        p[0] + miter[0] * offset * side,
+                        p[1] + miter[1] * offset * side,
+                    ]),
+                    color: IColor::new(color),
+                    sheet: ISheet::new(-1.0),
+                });
+            }
+        }
+        vertices
+    }
+}
+
+fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+    (dx * dx + dy * dy).sqrt()
+}
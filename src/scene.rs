@@ -0,0 +1,123 @@
+//! An optional way to split a game into menus, gameplay, pause screens and so on.
+//!
+//! Scenes are kept on a stack. Only the top scene is updated, but scenes below
+//! can still be drawn, so a pause menu can be shown over the paused game.
+//!
+//! ```ignore
+//! struct Gameplay;
+//!
+//! impl Scene<Name> for Gameplay {
+//!     fn update(&mut self, game: &mut Game<Name>, delta: f32) -> Transition<Name> {
+//!         if game.input.pressed(Name::Pause) {
+//!             Transition::Push(Box::new(PauseMenu))
+//!         } else {
+//!             Transition::None
+//!         }
+//!     }
+//!
+//!     fn draw(&mut self, game: &mut Game<Name>) {
+//!         // ...
+//!     }
+//! }
+//!
+//! game.run(SceneStack::new(Box::new(Gameplay)));
+//! ```
+//!
+//! Nothing stops you from writing the main loop by hand instead.
+
+use crate::Game;
+
+use std::hash::Hash;
+
+/// What the [SceneStack] should do after a scene has been updated.
+pub enum Transition<T> {
+    None,
+    /// Puts a new scene on top of this one.
+    Push(Box<dyn Scene<T>>),
+    /// Removes this scene, going back to the one below.
+    Pop,
+    /// Swaps this scene for a new one.
+    Replace(Box<dyn Scene<T>>),
+    /// Removes all scenes, which stops [Game::run].
+    Quit,
+}
+
+pub trait Scene<T> {
+    /// Called once per frame, but only for the scene on top.
+    fn update(&mut self, game: &mut Game<T>, delta: f32) -> Transition<T>;
+
+    /// Pushes what the scene wants drawn. Scenes are drawn from the bottom up.
+    fn draw(&mut self, game: &mut Game<T>);
+
+    /// If the scene below should be drawn too, like the game under a pause menu.
+    fn draw_below(&self) -> bool {
+        false
+    }
+}
+
+/// See the [module documentation](self).
+pub struct SceneStack<T> {
+    scenes: Vec<Box<dyn Scene<T>>>,
+}
+
+impl<T: Eq + Hash + Clone> SceneStack<T> {
+    pub fn new(scene: Box<dyn Scene<T>>) -> Self {
+        Self {
+            scenes: vec![scene],
+        }
+    }
+
+    pub fn push(&mut self, scene: Box<dyn Scene<T>>) {
+        self.scenes.push(scene);
+    }
+
+    pub fn pop(&mut self) -> Option<Box<dyn Scene<T>>> {
+        self.scenes.pop()
+    }
+
+    /// Swaps the top scene for a new one.
+    pub fn replace(&mut self, scene: Box<dyn Scene<T>>) -> Option<Box<dyn Scene<T>>> {
+        let old = self.scenes.pop();
+        self.scenes.push(scene);
+        old
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scenes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scenes.len()
+    }
+
+    /// Updates the top scene and applies its [Transition].
+    pub fn update(&mut self, game: &mut Game<T>) {
+        let delta = game.delta();
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.update(game, delta),
+            None => return,
+        };
+        match transition {
+            Transition::None => {}
+            Transition::Push(scene) => self.push(scene),
+            Transition::Pop => {
+                self.pop();
+            }
+            Transition::Replace(scene) => {
+                self.replace(scene);
+            }
+            Transition::Quit => self.scenes.clear(),
+        }
+    }
+
+    /// Draws the top scene, and the scenes below it that should be seen.
+    pub fn draw(&mut self, game: &mut Game<T>) {
+        let mut bottom = self.scenes.len().saturating_sub(1);
+        while bottom > 0 && self.scenes[bottom].draw_below() {
+            bottom -= 1;
+        }
+        for scene in self.scenes[bottom..].iter_mut() {
+            scene.draw(game);
+        }
+    }
+}
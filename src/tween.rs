@@ -0,0 +1,144 @@
+//! Moving between two values over time.
+//!
+//! ```ignore
+//! let mut tween = Tween::new([0.0, 0.0], [1.0, 2.0], 0.5).easing(Easing::EaseOut);
+//! loop {
+//!     let [x, y] = tween.update(game.delta());
+//!     sprite.at(x, y);
+//!     // ...
+//! }
+//! ```
+
+use crate::random::Easing;
+
+/// Things that can be blended linearly, see [Tween].
+pub trait Lerp: Copy {
+    /// `self` when `t` is 0 and `other` when `t` is 1.
+    fn lerp(self, other: Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(self, other: Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+macro_rules! impl_lerp_for_array {
+    ( $( $n:expr ),* ) => {
+        $(
+            impl Lerp for [f32; $n] {
+                fn lerp(mut self, other: Self, t: f32) -> Self {
+                    for (a, b) in self.iter_mut().zip(other.iter()) {
+                        *a = a.lerp(*b, t);
+                    }
+                    self
+                }
+            }
+        )*
+    }
+}
+
+// Positions, RGB-colors and RGBA-colors.
+impl_lerp_for_array!(2, 3, 4);
+
+/// Goes from `start` to `end` in `duration` seconds, eased along the way.
+#[derive(Clone, Copy, Debug)]
+pub struct Tween<T> {
+    pub start: T,
+    pub end: T,
+    pub duration: f32,
+    pub easing: Easing,
+    time: f32,
+}
+
+impl<T: Lerp> Tween<T> {
+    /// Moves at a constant speed, see [Tween::easing].
+    pub fn new(start: T, end: T, duration: f32) -> Self {
+        Self {
+            start,
+            end,
+            duration,
+            easing: Easing::Linear,
+            time: 0.0,
+        }
+    }
+
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Moves the tween forward and returns the new value.
+    /// Stays at `end` when it's done.
+    pub fn update(&mut self, delta: f32) -> T {
+        self.time = (self.time + delta).min(self.duration.max(0.0));
+        self.value()
+    }
+
+    pub fn value(&self) -> T {
+        self.start.lerp(self.end, self.easing.apply(self.progress()))
+    }
+
+    /// How far the tween has come, between 0 and 1, before easing.
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            1.0
+        } else {
+            (self.time / self.duration).min(1.0)
+        }
+    }
+
+    pub fn done(&self) -> bool {
+        self.progress() >= 1.0
+    }
+
+    /// Starts over from `start`.
+    pub fn reset(&mut self) {
+        self.time = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lerp_arrays_per_component() {
+        assert_eq!(1.0f32.lerp(3.0, 0.5), 2.0);
+        assert_eq!([0.0, 2.0].lerp([4.0, 0.0], 0.25), [1.0, 1.5]);
+        assert_eq!([0.0, 0.0, 0.0, 1.0].lerp([1.0, 1.0, 1.0, 0.0], 1.0), [1.0, 1.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn tween_moves_and_stops_at_the_end() {
+        let mut tween = Tween::new(0.0, 10.0, 2.0);
+        assert_eq!(tween.value(), 0.0);
+        assert_eq!(tween.update(0.5), 2.5);
+        assert!(!tween.done());
+        assert_eq!(tween.update(5.0), 10.0);
+        assert!(tween.done());
+        assert_eq!(tween.update(1.0), 10.0);
+    }
+
+    #[test]
+    fn tween_is_eased() {
+        let mut tween = Tween::new(0.0, 1.0, 1.0).easing(Easing::EaseIn);
+        assert_eq!(tween.update(0.5), 0.25);
+    }
+
+    #[test]
+    fn tween_without_duration_is_done() {
+        let tween = Tween::new(0.0, 1.0, 0.0);
+        assert!(tween.done());
+        assert_eq!(tween.value(), 1.0);
+    }
+
+    #[test]
+    fn reset_starts_over() {
+        let mut tween = Tween::new([0.0, 0.0], [1.0, 1.0], 1.0);
+        tween.update(1.0);
+        tween.reset();
+        assert_eq!(tween.value(), [0.0, 0.0]);
+        assert_eq!(tween.progress(), 0.0);
+    }
+}
@@ -1,4 +1,5 @@
 uniform mat4 view;
+uniform float pixel_snap;
 
 in vec2 co;
 
@@ -8,10 +9,24 @@ in vec2 scale;
 in vec4 color;
 in float sheet;
 in vec4 uv;
+in vec2 shear;
+in float depth;
 
 out vec4 v_color;
 out vec3 v_uv;
 
+vec2 skew(vec2 p, vec2 k) {
+    return vec2(p.x + k.x * p.y,
+                p.y + k.y * p.x);
+}
+
+vec2 snap(vec2 p) {
+    if (pixel_snap > 0.0) {
+        return floor(p / pixel_snap + 0.5) * pixel_snap;
+    }
+    return p;
+}
+
 vec2 rotate(vec2 p, float angle) {
     return vec2(p.x * cos(angle) - p.y * sin(angle),
                 p.x * sin(angle) + p.y * cos(angle));
@@ -25,5 +40,7 @@ void main() {
         mix(uv.y, uv.w, co.y + 0.5),
         sheet);
 
-  gl_Position = view * vec4(rotate(co * scale, rotation) + position, 0., 1.);
+  gl_Position = view * vec4(snap(rotate(skew(co * scale, shear), rotation) + position), 0., 1.);
+  // Higher depth is closer, and passes the depth test.
+  gl_Position.z = -depth;
 }
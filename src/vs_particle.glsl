@@ -1,5 +1,8 @@
 uniform mat4 view;
+uniform float pixel_snap;
 uniform float t;
+uniform vec4 tint;
+uniform int easing;
 
 in vec2 co;
 
@@ -23,6 +26,47 @@ out vec3 v_uv;
 
 in float sheet;
 in vec4 uv;
+in vec2 frames;
+
+vec2 snap(vec2 p) {
+    if (pixel_snap > 0.0) {
+        return floor(p / pixel_snap + 0.5) * pixel_snap;
+    }
+    return p;
+}
+
+// Has to match the Easing enum.
+float ease(float t) {
+    if (easing == 1) {
+        return t * t;
+    } else if (easing == 2) {
+        return t * (2.0 - t);
+    } else if (easing == 3) {
+        return t < 0.5 ? 2.0 * t * t : 1.0 - 2.0 * (1.0 - t) * (1.0 - t);
+    } else if (easing == 4) {
+        return t * t * (3.0 - 2.0 * t);
+    }
+    return t;
+}
+
+// How far a particle has moved after l seconds, starting with velocity v
+// and a constant acceleration a, slowed down by drag k. This is the exact
+// solution of dv/dt = a - k * v, so it doesn't depend on the frame rate.
+vec2 travel(vec2 v, vec2 a, float k, float l) {
+    if (abs(k) < 1e-5) {
+        return v * l + 0.5 * a * l * l;
+    }
+    vec2 terminal = a / k;
+    return terminal * l + (v - terminal) * (1.0 - exp(-k * l)) / k;
+}
+
+// Like travel, but for angles without acceleration.
+float turn(float w, float k, float l) {
+    if (abs(k) < 1e-5) {
+        return w * l;
+    }
+    return w * (1.0 - exp(-k * l)) / k;
+}
 
 vec2 rotate(vec2 p, float angle) {
     return vec2(p.x * cos(angle) - p.y * sin(angle),
@@ -30,24 +74,29 @@ vec2 rotate(vec2 p, float angle) {
 }
 
 void main() {
+    // Everything is a function of the particle's age.
     float l = t - spawn;
-    float drag_sum = (1.0 - exp(-l * drag)) / drag;
-    vec2 p = position + (velocity + acceleration * l) * drag_sum;
+    vec2 p = position + travel(velocity, acceleration, drag, l);
 
     float angle = angle_info.x;
     float angle_velocity = angle_info.y;
     float angle_drag = angle_info.z;
-    float angle_drag_sum = (1.0 - exp(-l * angle_drag)) / angle_drag;
-    float a = angle + angle_velocity * angle_drag_sum;
+    float a = angle + turn(angle_velocity, angle_drag, l);
 
     float lerp = min(1.0, l / lifetime);
-    vec2 s = mix(scale_extrems.xy, scale_extrems.zw, lerp);
+    float eased = ease(lerp);
+    vec2 s = mix(scale_extrems.xy, scale_extrems.zw, eased);
 
-    v_color = mix(start_color, end_color, lerp);
+    // The tint is the same for the whole system, so multiplying it in here
+    // gives the same color as in the fragment shader, which is shared with
+    // sprites that have no tint.
+    v_color = mix(start_color, end_color, eased) * tint;
+    float frame = min(floor(lerp * frames.x), frames.x - 1.0);
+    vec2 frame_uv = uv.xz + frame * frames.y;
     v_uv = vec3(
-            mix(uv.x, uv.z, co.x + 0.5),
+            mix(frame_uv.x, frame_uv.y, co.x + 0.5),
             mix(uv.y, uv.w, co.y + 0.5),
             sheet);
 
-    gl_Position = view * vec4(rotate(co * s, a) + p, 0.0, 1.0);
+    gl_Position = view * vec4(snap(rotate(co * s, a) + p), 0.0, 1.0);
 }